@@ -0,0 +1,1195 @@
+use std::{
+    cell::{Cell, Ref, RefCell},
+    rc::Rc,
+};
+
+use lol_html::{html_content::Element, HandlerResult};
+
+use crate::tags::{Namespace, Tag};
+
+/// What Selma remembers about an open element, once `lol_html` has moved past its start tag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ancestor {
+    tag: Tag,
+    // only set for tags missing from the `Tag` table
+    unknown_tag_name: Option<String>,
+    namespace: Namespace,
+    attributes: Vec<(String, String)>,
+}
+
+impl Ancestor {
+    pub fn from_element(element: &Element) -> Self {
+        let mut tag_name = element.tag_name();
+        tag_name.make_ascii_lowercase();
+        let tag = Tag::tag_from_tag_name(&tag_name);
+
+        Self {
+            tag,
+            unknown_tag_name: Tag::is_unknown(tag).then_some(tag_name),
+            namespace: Namespace::of(element),
+            attributes: element
+                .attributes()
+                .iter()
+                .map(|attr| (attr.name(), attr.value()))
+                .collect(),
+        }
+    }
+
+    pub fn tag(&self) -> Tag {
+        self.tag
+    }
+
+    pub fn tag_name(&self) -> &str {
+        self.unknown_tag_name.as_deref().unwrap_or(self.tag.name)
+    }
+
+    pub fn namespace(&self) -> Namespace {
+        self.namespace
+    }
+
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attr_name, _)| attr_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn classes(&self) -> impl Iterator<Item = &str> + '_ {
+        self.attribute("class")
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+    }
+}
+
+/// The elements enclosing whatever `lol_html` is currently parsing, outermost first. A single
+/// stack is kept per rewrite and shared by every handler.
+///
+/// `lol_html` doesn't build a tree: it only closes elements when it sees an end tag (along with
+/// anything left open inside them), so on its own an unclosed `<p>` or `<li>` would stay on the
+/// stack until its parent ends. To match what a browser would consider the ancestors, the
+/// stack also applies HTML's implied end tags when a start tag is seen: `close_implied_by` goes
+/// ahead of every other handler, and `push` after all of them.
+#[derive(Debug, Default)]
+pub struct AncestorStack {
+    stack: RefCell<Vec<Ancestor>>,
+    // parallel to `stack`, so an end tag can find its own element even after it was implicitly
+    // closed, or after something it contains was left open
+    ids: RefCell<Vec<usize>>,
+    next_id: Cell<usize>,
+    // selectors followed along as elements are pushed, and, parallel to `stack`, each
+    // element's `MatchState` for every one of them
+    tracked: Vec<AncestorSelector>,
+    states: RefCell<Vec<Vec<MatchState>>>,
+}
+
+/// A selector an `AncestorStack` keeps track of, as returned by `AncestorStack::track`.
+#[derive(Clone, Copy, Debug)]
+pub struct TrackedSelector(usize);
+
+impl AncestorStack {
+    // start tags which close an open `<p>`
+    const CLOSES_P: &'static [&'static str] = &[
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "center",
+        "details",
+        "dialog",
+        "dir",
+        "div",
+        "dl",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hgroup",
+        "hr",
+        "li",
+        "dd",
+        "dt",
+        "listing",
+        "main",
+        "menu",
+        "nav",
+        "ol",
+        "p",
+        "plaintext",
+        "pre",
+        "search",
+        "section",
+        "summary",
+        "table",
+        "ul",
+        "xmp",
+    ];
+
+    // an open `<p>` can't be closed from the other side of these
+    const BUTTON_SCOPE: &'static [&'static str] = &[
+        "applet",
+        "button",
+        "caption",
+        "html",
+        "marquee",
+        "object",
+        "table",
+        "td",
+        "template",
+        "th",
+        "annotation-xml",
+        "desc",
+        "foreignobject",
+        "mi",
+        "mn",
+        "mo",
+        "ms",
+        "mtext",
+    ];
+
+    // elements which stop the search for an open `<li>`, `<dd>` or `<dt>` to close
+    const SPECIAL: &'static [&'static str] = &[
+        "applet",
+        "area",
+        "article",
+        "aside",
+        "blockquote",
+        "body",
+        "button",
+        "caption",
+        "center",
+        "details",
+        "dialog",
+        "dir",
+        "dl",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hgroup",
+        "html",
+        "iframe",
+        "listing",
+        "main",
+        "marquee",
+        "menu",
+        "nav",
+        "object",
+        "ol",
+        "pre",
+        "search",
+        "section",
+        "select",
+        "summary",
+        "table",
+        "tbody",
+        "td",
+        "template",
+        "textarea",
+        "tfoot",
+        "th",
+        "thead",
+        "tr",
+        "ul",
+        "xmp",
+    ];
+
+    /// Has the stack work out whether each element it pushes matches `selector`, so that
+    /// checking an open element against it doesn't mean walking back through the stack.
+    pub fn track(&mut self, selector: AncestorSelector) -> TrackedSelector {
+        self.tracked.push(selector);
+        TrackedSelector(self.tracked.len() - 1)
+    }
+
+    pub fn borrow(&self) -> Ref<'_, Vec<Ancestor>> {
+        self.stack.borrow()
+    }
+
+    /// How many elements are open.
+    pub fn depth(&self) -> usize {
+        self.stack.borrow().len()
+    }
+
+    /// Whether any open element matches `tracked`.
+    pub fn is_within(&self, tracked: TrackedSelector) -> bool {
+        let selector = &self.tracked[tracked.0];
+        self.states
+            .borrow()
+            .last()
+            .is_some_and(|states| selector.is_within(&states[tracked.0]))
+    }
+
+    /// Whether `element`, whose start tag is being handled, matches `tracked`.
+    pub fn matches(&self, tracked: TrackedSelector, element: &Element) -> bool {
+        let selector = &self.tracked[tracked.0];
+        let states = self.states.borrow();
+        let parent = states.last().map(|states| &states[tracked.0]);
+
+        selector.is_match(&selector.advance(parent, &Ancestor::from_element(element)))
+    }
+
+    /// Closes whatever `element`'s start tag implicitly ends. This has to happen before anything
+    /// else handles the element, so that it isn't counted among its own ancestors, as an open
+    /// `<li>` would be for the next one.
+    ///
+    /// Only the implied end tags are modeled. Browsers also reopen formatting elements (`<b>`,
+    /// `<code>`, `<em>` and so on) which were closed this way, once more content follows, as
+    /// `<p><code>c<p>d` puts "d" in a new `<code>`. The stack doesn't, so "d" has no `<code>`
+    /// ancestor here.
+    pub fn close_implied_by(&self, element: &Element) {
+        if Namespace::of(element) != Namespace::Html {
+            return;
+        }
+
+        let mut tag_name = element.tag_name();
+        tag_name.make_ascii_lowercase();
+        self.close_implied(&tag_name);
+    }
+
+    /// Opens `element`, once every other handler is done with it; `close_implied_by` must have
+    /// been called for it first.
+    pub fn push(self: &Rc<Self>, element: &mut Element) -> HandlerResult {
+        let ancestor = Ancestor::from_element(element);
+
+        // void elements, and self-closing ones in SVG or MathML, never contain anything
+        if !element.can_have_content() {
+            return Ok(());
+        }
+
+        let states = {
+            let parent_states = self.states.borrow();
+            let parent_states = parent_states.last();
+            self.tracked
+                .iter()
+                .enumerate()
+                .map(|(i, selector)| {
+                    selector.advance(parent_states.map(|states| &states[i]), &ancestor)
+                })
+                .collect()
+        };
+
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.stack.borrow_mut().push(ancestor);
+        self.ids.borrow_mut().push(id);
+        self.states.borrow_mut().push(states);
+
+        let stack = Rc::clone(self);
+        element.on_end_tag(Box::new(move |_end_tag| {
+            // anything still open inside this element ends along with it
+            if let Some(index) = stack.position_of_id(id) {
+                stack.truncate(index);
+            }
+            Ok(())
+        }))
+    }
+
+    fn position_of_id(&self, id: usize) -> Option<usize> {
+        self.ids.borrow().iter().rposition(|&i| i == id)
+    }
+
+    fn pop(&self) {
+        self.stack.borrow_mut().pop();
+        self.ids.borrow_mut().pop();
+        self.states.borrow_mut().pop();
+    }
+
+    fn truncate(&self, len: usize) {
+        self.stack.borrow_mut().truncate(len);
+        self.ids.borrow_mut().truncate(len);
+        self.states.borrow_mut().truncate(len);
+    }
+
+    fn position_of(&self, tag_names: &[&str], stop_at: &[&str]) -> Option<usize> {
+        let stack = self.stack.borrow();
+        for (index, ancestor) in stack.iter().enumerate().rev() {
+            if tag_names.contains(&ancestor.tag_name()) {
+                return Some(index);
+            }
+            if stop_at.contains(&ancestor.tag_name()) {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn close(&self, tag_names: &[&str], stop_at: &[&str]) {
+        if let Some(index) = self.position_of(tag_names, stop_at) {
+            self.truncate(index);
+        }
+    }
+
+    /// Closes whatever a start tag for `tag_name` implicitly ends, following the "in body"
+    /// and table insertion modes of the HTML parsing spec.
+    fn close_implied(&self, tag_name: &str) {
+        match tag_name {
+            "li" => self.close_list_item(&["li"]),
+            "dd" | "dt" => self.close_list_item(&["dd", "dt"]),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p();
+                // headings can't be nested in one another
+                self.close_current(&["h1", "h2", "h3", "h4", "h5", "h6"]);
+            }
+            "option" => self.close_current(&["option"]),
+            "optgroup" => {
+                self.close_current(&["option"]);
+                self.close_current(&["optgroup"]);
+            }
+            "caption" | "colgroup" | "tbody" | "thead" | "tfoot" => self.close_within_table(&[
+                "caption", "colgroup", "tbody", "thead", "tfoot", "tr", "td", "th",
+            ]),
+            "tr" => self.close_within_table(&["caption", "colgroup", "tr", "td", "th"]),
+            "td" | "th" => self.close_within_table(&["caption", "colgroup", "td", "th"]),
+            _ if Self::CLOSES_P.contains(&tag_name) => self.close_p(),
+            _ => {}
+        }
+    }
+
+    fn close_p(&self) {
+        self.close(&["p"], Self::BUTTON_SCOPE);
+    }
+
+    fn close_list_item(&self, tag_names: &[&str]) {
+        let stop_at: Vec<&str> = Self::SPECIAL
+            .iter()
+            .copied()
+            .filter(|t| !tag_names.contains(t))
+            .collect();
+        self.close(tag_names, &stop_at);
+        self.close_p();
+    }
+
+    fn close_current(&self, tag_names: &[&str]) {
+        let current_matches = self
+            .stack
+            .borrow()
+            .last()
+            .is_some_and(|a| tag_names.contains(&a.tag_name()));
+        if current_matches {
+            self.pop();
+        }
+    }
+
+    fn close_within_table(&self, tag_names: &[&str]) {
+        let index = {
+            let stack = self.stack.borrow();
+            let Some(table) = stack.iter().rposition(|a| a.tag_name() == "table") else {
+                return;
+            };
+            stack[table + 1..]
+                .iter()
+                .position(|a| tag_names.contains(&a.tag_name()))
+                .map(|i| table + 1 + i)
+        };
+        if let Some(index) = index {
+            self.truncate(index);
+        }
+    }
+}
+
+/// A CSS selector list which can be tested against a stack of `Ancestor`s, e.g.
+/// `code, pre, a, .no-autolink, [data-skip]`.
+///
+/// `lol_html` only matches selectors against the element it's currently parsing, so this
+/// covers the subset of CSS that makes sense for something which has already been parsed:
+/// type, universal, id, class and attribute selectors, joined by descendant or child
+/// combinators. Pseudo-classes and sibling combinators are rejected.
+///
+/// Type and universal selectors may be namespaced with the `html`, `svg` or `math` prefixes,
+/// as in `svg|title` or `math|*`; without one, they match elements in any namespace.
+#[derive(Clone, Debug)]
+pub struct AncestorSelector {
+    selectors: Vec<ComplexSelector>,
+}
+
+#[derive(Clone, Debug)]
+struct ComplexSelector {
+    // the rightmost compound selector is the subject; each compound's combinator links
+    // it to the compound before it
+    compounds: Vec<(Combinator, CompoundSelector)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Clone, Debug, Default)]
+struct CompoundSelector {
+    // `None` matches any namespace
+    namespace: Option<Namespace>,
+    tag_name: Option<String>,
+    ids: Vec<String>,
+    classes: Vec<String>,
+    attributes: Vec<AttributeSelector>,
+}
+
+#[derive(Clone, Debug)]
+struct AttributeSelector {
+    name: String,
+    operator: Option<(AttributeOperator, String)>,
+    case_insensitive: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AttributeOperator {
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+/// How far along an `AncestorSelector` an element gets, given its ancestors: for each compound
+/// selector of each selector in the list, whether the element matches it along with everything
+/// to its left (`matched`), and whether the element or any of its ancestors does (`within`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchState {
+    matched: Vec<bool>,
+    within: Vec<bool>,
+}
+
+impl AncestorSelector {
+    pub fn parse(css: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: css.chars().collect(),
+            pos: 0,
+        };
+        let mut selectors = vec![];
+
+        loop {
+            selectors.push(parser.complex_selector()?);
+            parser.skip_whitespace();
+            match parser.next() {
+                None => break,
+                Some(',') => {}
+                Some(c) => return Err(format!("unexpected `{c}`")),
+            }
+        }
+
+        Ok(Self { selectors })
+    }
+
+    /// Joins several selector lists into one.
+    pub fn concat(selectors: impl IntoIterator<Item = Self>) -> Self {
+        Self {
+            selectors: selectors.into_iter().flat_map(|s| s.selectors).collect(),
+        }
+    }
+
+    /// Whether the last element of `stack` matches, given the rest of `stack` as its ancestors.
+    pub fn matches(&self, stack: &[Ancestor]) -> bool {
+        self.states(stack)
+            .last()
+            .is_some_and(|state| self.is_match(&state))
+    }
+
+    /// Whether any element of `stack` matches.
+    pub fn matches_any(&self, stack: &[Ancestor]) -> bool {
+        self.states(stack)
+            .last()
+            .is_some_and(|state| self.is_within(&state))
+    }
+
+    /// Whether `subject` matches, given `ancestors`; like `matches`, for an element which isn't
+    /// on the stack.
+    pub fn matches_subject(&self, subject: &Ancestor, ancestors: &[Ancestor]) -> bool {
+        let parent = self.states(ancestors).last();
+        self.is_match(&self.advance(parent.as_ref(), subject))
+    }
+
+    /// The `MatchState` of each element of `stack`, outermost first.
+    pub fn states<'s>(&'s self, stack: &'s [Ancestor]) -> impl Iterator<Item = MatchState> + 's {
+        stack
+            .iter()
+            .scan(None, |parent: &mut Option<MatchState>, ancestor| {
+                let state = self.advance(parent.as_ref(), ancestor);
+                *parent = Some(state.clone());
+                Some(state)
+            })
+    }
+
+    /// The `MatchState` of `subject`, given the state of its parent (`None` at the root).
+    ///
+    /// Each compound selector's state only depends on the parent's state for the compound
+    /// before it, so this takes time in proportion to the length of the selector list, however
+    /// deep `subject` is.
+    pub fn advance(&self, parent: Option<&MatchState>, subject: &Ancestor) -> MatchState {
+        let mut state = MatchState::default();
+        let mut index = 0;
+
+        for selector in &self.selectors {
+            for (position, (combinator, compound)) in selector.compounds.iter().enumerate() {
+                let matched = compound.matches(subject)
+                    && (position == 0
+                        || parent.is_some_and(|parent| match combinator {
+                            Combinator::Child => parent.matched[index - 1],
+                            Combinator::Descendant => parent.within[index - 1],
+                        }));
+                let within = matched || parent.is_some_and(|parent| parent.within[index]);
+
+                state.matched.push(matched);
+                state.within.push(within);
+                index += 1;
+            }
+        }
+
+        state
+    }
+
+    /// Whether the element `state` belongs to matches.
+    pub fn is_match(&self, state: &MatchState) -> bool {
+        self.subject_indices().any(|index| state.matched[index])
+    }
+
+    /// Whether the element `state` belongs to, or any of its ancestors, matches.
+    pub fn is_within(&self, state: &MatchState) -> bool {
+        self.subject_indices().any(|index| state.within[index])
+    }
+
+    // where each selector's subject (its last compound) is, in a `MatchState`
+    fn subject_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.selectors.iter().scan(0, |end, selector| {
+            *end += selector.compounds.len();
+            Some(*end - 1)
+        })
+    }
+
+    /// Whether any part of this selector list is restricted to a namespace.
+    pub fn is_namespaced(&self) -> bool {
+        self.selectors.iter().any(|selector| {
+            selector
+                .compounds
+                .iter()
+                .any(|(_, compound)| compound.namespace.is_some())
+        })
+    }
+
+    /// This selector list as CSS, with any namespace prefixes dropped, for `lol_html`.
+    pub fn to_css_without_namespaces(&self) -> String {
+        let mut css = String::new();
+
+        for (i, selector) in self.selectors.iter().enumerate() {
+            if i > 0 {
+                css.push_str(", ");
+            }
+            for (j, (combinator, compound)) in selector.compounds.iter().enumerate() {
+                if j > 0 {
+                    css.push_str(match combinator {
+                        Combinator::Descendant => " ",
+                        Combinator::Child => " > ",
+                    });
+                }
+                compound.write_css(&mut css);
+            }
+        }
+
+        css
+    }
+}
+
+impl CompoundSelector {
+    fn is_empty(&self) -> bool {
+        self.tag_name.is_none()
+            && self.ids.is_empty()
+            && self.classes.is_empty()
+            && self.attributes.is_empty()
+    }
+
+    fn matches(&self, ancestor: &Ancestor) -> bool {
+        if self
+            .namespace
+            .is_some_and(|namespace| namespace != ancestor.namespace())
+        {
+            return false;
+        }
+        if let Some(tag_name) = &self.tag_name {
+            if !tag_name.eq_ignore_ascii_case(ancestor.tag_name()) {
+                return false;
+            }
+        }
+
+        self.ids
+            .iter()
+            .all(|id| ancestor.attribute("id") == Some(id.as_str()))
+            && self
+                .classes
+                .iter()
+                .all(|class| ancestor.classes().any(|c| c == class))
+            && self.attributes.iter().all(|attr| attr.matches(ancestor))
+    }
+
+    fn write_css(&self, css: &mut String) {
+        match &self.tag_name {
+            Some(tag_name) => write_ident(css, tag_name),
+            None if self.is_empty() => css.push('*'),
+            None => {}
+        }
+        for id in &self.ids {
+            css.push('#');
+            write_ident(css, id);
+        }
+        for class in &self.classes {
+            css.push('.');
+            write_ident(css, class);
+        }
+        for attr in &self.attributes {
+            attr.write_css(css);
+        }
+    }
+}
+
+impl AttributeSelector {
+    fn write_css(&self, css: &mut String) {
+        css.push('[');
+        write_ident(css, &self.name);
+        if let Some((operator, value)) = &self.operator {
+            css.push_str(match operator {
+                AttributeOperator::Equals => "=",
+                AttributeOperator::Includes => "~=",
+                AttributeOperator::DashMatch => "|=",
+                AttributeOperator::Prefix => "^=",
+                AttributeOperator::Suffix => "$=",
+                AttributeOperator::Substring => "*=",
+            });
+            css.push('"');
+            for c in value.chars() {
+                if c == '"' || c == '\\' {
+                    css.push('\\');
+                }
+                css.push(c);
+            }
+            css.push('"');
+            if self.case_insensitive {
+                css.push_str(" i");
+            }
+        }
+        css.push(']');
+    }
+
+    fn matches(&self, ancestor: &Ancestor) -> bool {
+        let Some(actual) = ancestor.attribute(&self.name) else {
+            return false;
+        };
+        let Some((operator, expected)) = &self.operator else {
+            return true;
+        };
+
+        let (actual, expected) = if self.case_insensitive {
+            (actual.to_lowercase(), expected.to_lowercase())
+        } else {
+            (actual.to_string(), expected.clone())
+        };
+
+        match operator {
+            AttributeOperator::Equals => actual == expected,
+            AttributeOperator::Includes => actual.split_ascii_whitespace().any(|v| v == expected),
+            AttributeOperator::DashMatch => {
+                actual == expected || actual.starts_with(&format!("{expected}-"))
+            }
+            // an empty value never matches the substring operators
+            AttributeOperator::Prefix => !expected.is_empty() && actual.starts_with(&expected),
+            AttributeOperator::Suffix => !expected.is_empty() && actual.ends_with(&expected),
+            AttributeOperator::Substring => !expected.is_empty() && actual.contains(&expected),
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn complex_selector(&mut self) -> Result<ComplexSelector, String> {
+        self.skip_whitespace();
+        let mut compounds = vec![(Combinator::Descendant, self.compound_selector()?)];
+
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            let combinator = match self.peek() {
+                None | Some(',') => break,
+                Some('>') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    Combinator::Child
+                }
+                Some(c @ ('+' | '~')) => {
+                    return Err(format!("the `{c}` combinator is not supported"));
+                }
+                Some(_) if had_whitespace => Combinator::Descendant,
+                Some(c) => return Err(format!("unexpected `{c}`")),
+            };
+            compounds.push((combinator, self.compound_selector()?));
+        }
+
+        Ok(ComplexSelector { compounds })
+    }
+
+    fn compound_selector(&mut self) -> Result<CompoundSelector, String> {
+        let mut compound = CompoundSelector::default();
+        let mut universal = false;
+
+        match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                universal = true;
+            }
+            Some(c) if is_ident_start(c) => compound.tag_name = Some(self.ident()?),
+            _ => {}
+        }
+
+        // what came before was a namespace prefix, as in `svg|title` or `*|title`
+        if self.peek() == Some('|') && (universal || compound.tag_name.is_some()) {
+            self.pos += 1;
+            if let Some(prefix) = compound.tag_name.take() {
+                compound.namespace = Some(
+                    Namespace::from_prefix(&prefix)
+                        .ok_or_else(|| format!("unknown namespace prefix `{prefix}`"))?,
+                );
+            }
+            universal = false;
+
+            match self.peek() {
+                Some('*') => {
+                    self.pos += 1;
+                    universal = true;
+                }
+                Some(c) if is_ident_start(c) => compound.tag_name = Some(self.ident()?),
+                _ => return Err("expected a type selector after `|`".to_string()),
+            }
+        }
+
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.pos += 1;
+                    compound.ids.push(self.ident()?);
+                }
+                Some('.') => {
+                    self.pos += 1;
+                    compound.classes.push(self.ident()?);
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    compound.attributes.push(self.attribute_selector()?);
+                }
+                Some(':') => return Err("pseudo-classes are not supported".to_string()),
+                _ => break,
+            }
+        }
+
+        if compound.is_empty() && !universal {
+            return Err(match self.peek() {
+                None => "expected a selector".to_string(),
+                Some(c) => format!("unexpected `{c}`"),
+            });
+        }
+
+        Ok(compound)
+    }
+
+    fn attribute_selector(&mut self) -> Result<AttributeSelector, String> {
+        self.skip_whitespace();
+        let name = self.ident()?.to_ascii_lowercase();
+        self.skip_whitespace();
+
+        let operator = match self.next() {
+            Some(']') => {
+                return Ok(AttributeSelector {
+                    name,
+                    operator: None,
+                    case_insensitive: false,
+                });
+            }
+            Some('=') => AttributeOperator::Equals,
+            Some(c @ ('~' | '|' | '^' | '$' | '*')) => {
+                if self.next() != Some('=') {
+                    return Err(format!("expected `=` after `{c}`"));
+                }
+                match c {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    _ => AttributeOperator::Substring,
+                }
+            }
+            _ => return Err(format!("invalid attribute selector for `{name}`")),
+        };
+
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.pos += 1;
+                self.string(quote)?
+            }
+            _ => self.ident()?,
+        };
+        self.skip_whitespace();
+
+        let case_insensitive = match self.peek() {
+            Some('i' | 'I') => {
+                self.pos += 1;
+                self.skip_whitespace();
+                true
+            }
+            Some('s' | 'S') => {
+                self.pos += 1;
+                self.skip_whitespace();
+                false
+            }
+            _ => false,
+        };
+
+        if self.next() != Some(']') {
+            return Err(format!("unterminated attribute selector for `{name}`"));
+        }
+
+        Ok(AttributeSelector {
+            name,
+            operator: Some((operator, value)),
+            case_insensitive,
+        })
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        let mut ident = String::new();
+
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                self.pos += 1;
+                match self.next() {
+                    Some(escaped) => ident.push(escaped),
+                    None => return Err("unterminated escape".to_string()),
+                }
+            } else if is_ident_char(c) {
+                self.pos += 1;
+                ident.push(c);
+            } else {
+                break;
+            }
+        }
+
+        if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(match self.peek() {
+                None => "expected a name".to_string(),
+                Some(c) => format!("expected a name, found `{c}`"),
+            });
+        }
+
+        Ok(ident)
+    }
+
+    fn string(&mut self, quote: char) -> Result<String, String> {
+        let mut string = String::new();
+
+        loop {
+            match self.next() {
+                None => return Err("unterminated string".to_string()),
+                Some('\\') => match self.next() {
+                    Some(escaped) => string.push(escaped),
+                    None => return Err("unterminated string".to_string()),
+                },
+                Some(c) if c == quote => return Ok(string),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+}
+
+fn write_ident(css: &mut String, ident: &str) {
+    for c in ident.chars() {
+        if !is_ident_char(c) {
+            css.push('\\');
+        }
+        css.push(c);
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '-' || c == '\\' || !c.is_ascii()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || !c.is_ascii()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use lol_html::{element, text, HtmlRewriter, Settings};
+
+    use super::*;
+
+    fn ancestor(tag_name: &str, namespace: Namespace, attributes: &[(&str, &str)]) -> Ancestor {
+        let tag = Tag::tag_from_tag_name(tag_name);
+
+        Ancestor {
+            tag,
+            unknown_tag_name: Tag::is_unknown(tag).then(|| tag_name.to_string()),
+            namespace,
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn html(tag_name: &str) -> Ancestor {
+        ancestor(tag_name, Namespace::Html, &[])
+    }
+
+    fn selector(css: &str) -> AncestorSelector {
+        AncestorSelector::parse(css).unwrap()
+    }
+
+    #[test]
+    fn it_parses_and_writes_selectors_back_out() {
+        assert_eq!(
+            selector(r#"pre > code, .no-autolink,a[data-skip],  div  p#x.y[lang|="en" i]"#)
+                .to_css_without_namespaces(),
+            r#"pre > code, .no-autolink, a[data-skip], div p#x.y[lang|="en" i]"#
+        );
+        assert_eq!(
+            selector("svg|title, math|*, *").to_css_without_namespaces(),
+            "title, *, *"
+        );
+        assert!(selector("svg|title").is_namespaced());
+        assert!(!selector("title").is_namespaced());
+    }
+
+    #[test]
+    fn it_rejects_what_it_cannot_match() {
+        for css in [
+            "", "a,", "a:hover", "a + b", "a ~ b", "foo|a", "[href", "a[href^]", "1a", "a)",
+        ] {
+            assert!(AncestorSelector::parse(css).is_err(), "{css:?} parsed");
+        }
+    }
+
+    #[test]
+    fn it_matches_compound_selectors() {
+        let link = [ancestor(
+            "a",
+            Namespace::Html,
+            &[
+                ("class", "link  external"),
+                ("HREF", "https://example.com"),
+                ("id", "top"),
+            ],
+        )];
+
+        for css in [
+            "a",
+            "A",
+            "*",
+            "html|a",
+            ".external",
+            "a.link.external",
+            "#top",
+            "[href]",
+            "[href^=https]",
+            "[href$='.com']",
+            "[href*=example]",
+            "[class~=link]",
+            "[href='HTTPS://EXAMPLE.COM' i]",
+        ] {
+            assert!(selector(css).matches(&link), "{css:?} didn't match");
+        }
+        for css in [
+            "b",
+            "svg|a",
+            ".lin",
+            "#bottom",
+            "[title]",
+            "[href^='']",
+            "[href='HTTPS://EXAMPLE.COM']",
+        ] {
+            assert!(!selector(css).matches(&link), "{css:?} matched");
+        }
+    }
+
+    #[test]
+    fn it_matches_combinators() {
+        let stack = [html("div"), html("pre"), html("span"), html("code")];
+
+        assert!(selector("code").matches(&stack));
+        assert!(selector("span > code").matches(&stack));
+        assert!(selector("div code").matches(&stack));
+        assert!(selector("div > pre span > code").matches(&stack));
+        assert!(!selector("div > code").matches(&stack));
+        assert!(!selector("pre > code").matches(&stack));
+        assert!(!selector("code span").matches(&stack));
+
+        assert!(selector("pre").matches_any(&stack));
+        assert!(selector("div > pre").matches_any(&stack));
+        assert!(!selector("p").matches_any(&stack));
+        assert!(!selector("span > pre").matches_any(&stack));
+
+        assert!(selector("pre > span > code").matches_subject(&html("code"), &stack[..3]));
+        assert!(!selector("div > code").matches_subject(&html("code"), &stack[..3]));
+    }
+
+    #[test]
+    fn it_matches_namespaces() {
+        let stack = [
+            html("p"),
+            ancestor("svg", Namespace::Svg, &[]),
+            ancestor("title", Namespace::Svg, &[]),
+        ];
+
+        assert!(selector("svg|title").matches(&stack));
+        assert!(selector("html|p svg|*").matches(&stack));
+        assert!(!selector("html|title").matches(&stack));
+        assert!(!selector("math|*").matches_any(&stack));
+    }
+
+    #[test]
+    fn it_matches_deep_stacks_without_backtracking() {
+        // each compound could match at any depth, which made naive matching take exponential time
+        let stack = vec![html("div"); 2_000];
+        let selector = selector("div div div div div div div div p, div > div > div > p");
+
+        assert!(!selector.matches(&stack));
+        assert!(!selector.matches_any(&stack));
+        assert_eq!(selector.states(&stack).count(), stack.len());
+    }
+
+    #[test]
+    fn it_keeps_the_state_of_later_matches() {
+        let selector = selector("ul li, p");
+        let stack = [html("ul"), html("li"), html("b")];
+        let states: Vec<MatchState> = selector.states(&stack).collect();
+
+        assert!(!selector.is_match(&states[0]));
+        assert!(selector.is_match(&states[1]));
+        assert!(!selector.is_match(&states[2]));
+        assert!(selector.is_within(&states[2]));
+    }
+
+    // the text of each text chunk, and whether it was within `ignore_text_within`
+    fn texts_within(html: &str, ignore_text_within: &str) -> Vec<(String, bool)> {
+        let mut stack = AncestorStack::default();
+        let tracked = stack.track(selector(ignore_text_within));
+        let stack = Rc::new(stack);
+        let texts = RefCell::new(vec![]);
+
+        {
+            let settings = Settings::new()
+                .append_element_content_handler(element!("*", |el| {
+                    stack.close_implied_by(el);
+                    Ok(())
+                }))
+                .append_element_content_handler(text!("*", |t| {
+                    if !t.as_str().is_empty() {
+                        texts
+                            .borrow_mut()
+                            .push((t.as_str().to_string(), stack.is_within(tracked)));
+                    }
+                    Ok(())
+                }))
+                .append_element_content_handler(element!("*", |el| stack.push(el)));
+            let mut rewriter = HtmlRewriter::new(settings, |_: &[u8]| {});
+            rewriter.write(html.as_bytes()).unwrap();
+            rewriter.end().unwrap();
+        }
+
+        texts.into_inner()
+    }
+
+    #[test]
+    fn it_tracks_selectors_as_elements_are_pushed() {
+        assert_eq!(
+            texts_within(
+                r#"<div><pre><b>a</b></pre>b<span class="skip"><i>c</i></span><i>d</i></div>"#,
+                "pre, .skip > i"
+            ),
+            [
+                ("a".to_string(), true),
+                ("b".to_string(), false),
+                ("c".to_string(), true),
+                ("d".to_string(), false)
+            ]
+        );
+    }
+
+    // a browser would reopen `<code>` around "b" and "d", reconstructing the formatting
+    // elements that were closed implicitly; the stack doesn't, and this pins down that it doesn't
+    #[test]
+    fn implicitly_closed_elements_stay_closed_without_reconstructing_formatting() {
+        assert_eq!(
+            texts_within("<ul><li><code>a<li>b</ul><p><code>c<p>d", "code"),
+            [
+                ("a".to_string(), true),
+                ("b".to_string(), false),
+                ("c".to_string(), true),
+                ("d".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn it_applies_implied_end_tags_before_an_element_is_handled() {
+        let stack = Rc::new(AncestorStack::default());
+        let depths = RefCell::new(vec![]);
+
+        {
+            let settings = Settings::new()
+                .append_element_content_handler(element!("*", |el| {
+                    stack.close_implied_by(el);
+                    depths.borrow_mut().push(stack.depth());
+                    Ok(())
+                }))
+                .append_element_content_handler(element!("*", |el| stack.push(el)));
+            let mut rewriter = HtmlRewriter::new(settings, |_: &[u8]| {});
+            rewriter
+                .write("<ul><li>a<li>b<li><p>c<p>d</ul><br><p>e".as_bytes())
+                .unwrap();
+            rewriter.end().unwrap();
+        }
+
+        // the depth of each element's parent
+        assert_eq!(depths.into_inner(), [0, 1, 1, 1, 2, 2, 0, 0]);
+    }
+}
@@ -0,0 +1,113 @@
+use magnus::{value::ReprValue, Error, RHash, RString, Ruby};
+
+use crate::{sanitizer_config::invalid, tags::Namespace};
+
+pub const DEFAULT_ID_PREFIX: &str = "user-content-";
+
+// attributes which refer to elements by id, as a space-separated list
+const ID_REFERENCE_ATTRIBUTES: &[&str] = &[
+    "aria-activedescendant",
+    "aria-controls",
+    "aria-describedby",
+    "aria-details",
+    "aria-errormessage",
+    "aria-flowto",
+    "aria-labelledby",
+    "aria-owns",
+    "for",
+    "form",
+    "headers",
+    "list",
+    "popovertarget",
+];
+
+// SVG attributes which refer to gradients, clip paths and the like as `url(#id)`
+const URL_REFERENCE_ATTRIBUTES: &[&str] = &[
+    "clip-path",
+    "fill",
+    "filter",
+    "marker-end",
+    "marker-mid",
+    "marker-start",
+    "mask",
+    "stroke",
+];
+
+/// `clobber_protection:` and `id_prefix:`, compiled: the prefix given to `id` and `name` values,
+/// so that they can't clobber globals like `document.cookie` or `window.config`.
+#[derive(Debug)]
+pub struct IdPrefix(String);
+
+impl IdPrefix {
+    pub fn from_config(config: RHash) -> Result<Option<Self>, Error> {
+        let ruby = Ruby::get().unwrap();
+
+        let id_prefix = match config.get(ruby.to_symbol("id_prefix")) {
+            None => None,
+            Some(id_prefix) if id_prefix.is_nil() => None,
+            Some(id_prefix) => match RString::from_value(id_prefix) {
+                Some(prefix) if prefix.len() > 0 => Some(prefix.to_string()?),
+                _ => {
+                    return Err(invalid(format!(
+                        "`id_prefix` must be a non-empty String, like \"user-content-\", not `{}`",
+                        id_prefix.inspect()
+                    )))
+                }
+            },
+        };
+        // giving an `id_prefix` turns the protection on, unless it's explicitly turned off
+        let clobber_protection = match config.get(ruby.to_symbol("clobber_protection")) {
+            Some(value) if !value.is_nil() => value.to_bool(),
+            _ => id_prefix.is_some(),
+        };
+
+        Ok(clobber_protection
+            .then(|| IdPrefix(id_prefix.unwrap_or_else(|| DEFAULT_ID_PREFIX.to_string()))))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// `attr_val` (unescaped) with the prefix added, if `attr_name` on `tag_name`, an element in
+    /// `namespace`, holds an id or a name, or refers to one, as a fragment link (like
+    /// `href="#notes"`) does. Values which already start with the prefix are left alone, so that
+    /// sanitizing twice doesn't prefix twice.
+    pub fn prefix(
+        &self,
+        namespace: Namespace,
+        tag_name: &str,
+        attr_name: &str,
+        attr_val: &str,
+    ) -> Option<String> {
+        match attr_name {
+            "id" => self.prefix_id(attr_val),
+            // `<meta name>` names metadata, not the element
+            "name" if tag_name != "meta" => self.prefix_id(attr_val),
+            "href" | "xlink:href" | "usemap" => {
+                let id = attr_val.strip_prefix('#')?;
+                self.prefix_id(id).map(|id| format!("#{id}"))
+            }
+            _ if ID_REFERENCE_ATTRIBUTES.contains(&attr_name) => {
+                let ids: Vec<String> = attr_val
+                    .split_ascii_whitespace()
+                    .map(|id| self.prefix_id(id).unwrap_or_else(|| id.to_string()))
+                    .collect();
+                Some(ids.join(" "))
+            }
+            _ if namespace == Namespace::Svg && URL_REFERENCE_ATTRIBUTES.contains(&attr_name) => {
+                let id = attr_val.trim().strip_prefix("url(#")?.strip_suffix(')')?;
+                self.prefix_id(id).map(|id| format!("url(#{id})"))
+            }
+            _ => None,
+        }
+    }
+
+    fn prefix_id(&self, id: &str) -> Option<String> {
+        if id.is_empty() || id.starts_with(&self.0) {
+            return None;
+        }
+
+        Some(format!("{}{id}", self.0))
+    }
+}
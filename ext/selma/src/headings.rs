@@ -0,0 +1,236 @@
+use std::{borrow::Cow, cell::RefCell, collections::HashSet, rc::Rc};
+
+use lol_html::{element, html_content::Element, text, ElementContentHandlers, Selector};
+use magnus::{value::ReprValue, Error, Integer, RHash, RString, Ruby};
+
+use crate::sanitizer_config::invalid;
+
+const HEADINGS_CSS: &str = "h1, h2, h3, h4, h5, h6";
+
+// what a heading with no usable text is called
+const EMPTY_SLUG: &str = "section";
+
+/// `headings:`, compiled: how many levels to shift headings by, and the prefix for the ids
+/// they're given, if they're given any.
+#[derive(Debug, Default)]
+pub struct Headings {
+    shift: i64,
+    id_prefix: Option<String>,
+}
+
+/// The text of each heading in a document, in order. One pass collects it, so that the next
+/// can give the headings their ids, since a heading's start tag goes out before its text is seen.
+#[derive(Debug, Default)]
+pub struct HeadingTexts {
+    texts: Vec<String>,
+    // the headings whose end tag hasn't been seen yet, innermost last
+    open: Vec<usize>,
+}
+
+impl HeadingTexts {
+    /// Whether `other` collected the same headings, with the same text, in the same order.
+    pub fn matches(&self, other: &HeadingTexts) -> bool {
+        self.texts == other.texts
+    }
+}
+
+impl Headings {
+    /// `headings:` from `config`. Ids get `default_id_prefix` unless `headings[:id_prefix]` says
+    /// otherwise.
+    pub fn from_config(config: RHash, default_id_prefix: &str) -> Result<Self, Error> {
+        let ruby = Ruby::get().unwrap();
+        let Some(headings) = config.get(ruby.to_symbol("headings")) else {
+            return Ok(Self::default());
+        };
+        if headings.is_nil() {
+            return Ok(Self::default());
+        }
+        let Some(headings) = RHash::from_value(headings) else {
+            return Err(invalid(format!(
+                "`headings` must be a Hash, not `{}`",
+                headings.inspect()
+            )));
+        };
+
+        let shift = match headings.get(ruby.to_symbol("shift")) {
+            None => 0,
+            Some(shift) => match Integer::from_value(shift) {
+                Some(shift) => shift.to_i64()?,
+                None => {
+                    return Err(invalid(format!(
+                        "`headings[:shift]` must be an Integer, not `{}`",
+                        shift.inspect()
+                    )))
+                }
+            },
+        };
+        let ids = match headings.get(ruby.to_symbol("ids")) {
+            None => false,
+            Some(ids) if ids.is_kind_of(ruby.class_true_class()) => true,
+            Some(ids) if ids.is_kind_of(ruby.class_false_class()) => false,
+            Some(ids) => {
+                return Err(invalid(format!(
+                    "`headings[:ids]` must be `true` or `false`, not `{}`",
+                    ids.inspect()
+                )))
+            }
+        };
+        let id_prefix = match headings.get(ruby.to_symbol("id_prefix")) {
+            None => default_id_prefix.to_string(),
+            Some(prefix) => match RString::from_value(prefix) {
+                Some(prefix) => prefix.to_string()?,
+                None => {
+                    return Err(invalid(format!(
+                        "`headings[:id_prefix]` must be a String, like \"user-content-\", not `{}`",
+                        prefix.inspect()
+                    )))
+                }
+            },
+        };
+
+        Ok(Headings {
+            shift,
+            id_prefix: ids.then_some(id_prefix),
+        })
+    }
+
+    pub fn shifts(&self) -> bool {
+        self.shift != 0
+    }
+
+    pub fn gives_ids(&self) -> bool {
+        self.id_prefix.is_some()
+    }
+
+    /// The name a heading named `tag_name` (lowercase) is shifted to, if it's a heading and the
+    /// shift changes its level. Levels stop at `h1` and `h6`.
+    pub fn shifted(&self, tag_name: &str) -> Option<String> {
+        let level = Self::level(tag_name)?;
+        let shifted = level.saturating_add(self.shift).clamp(1, 6);
+
+        (shifted != level).then(|| format!("h{shifted}"))
+    }
+
+    fn level(tag_name: &str) -> Option<i64> {
+        match tag_name.as_bytes() {
+            [b'h', level @ b'1'..=b'6'] => Some(i64::from(level - b'0')),
+            _ => None,
+        }
+    }
+
+    /// Handlers which collect the text of every heading into `heading_texts`.
+    pub fn collection_handlers<'a>(
+        heading_texts: &Rc<RefCell<HeadingTexts>>,
+    ) -> Vec<(Cow<'a, Selector>, ElementContentHandlers<'a>)> {
+        let element_texts = Rc::clone(heading_texts);
+        let text_texts = Rc::clone(heading_texts);
+
+        vec![
+            element!(HEADINGS_CSS, move |el| {
+                Self::start_heading(&element_texts, el);
+                Ok(())
+            }),
+            text!(HEADINGS_CSS, move |t| {
+                let mut heading_texts = text_texts.borrow_mut();
+                if let Some(&index) = heading_texts.open.last() {
+                    heading_texts.texts[index].push_str(t.as_str());
+                }
+
+                Ok(())
+            }),
+        ]
+    }
+
+    fn start_heading(heading_texts: &Rc<RefCell<HeadingTexts>>, element: &mut Element) {
+        {
+            let mut heading_texts = heading_texts.borrow_mut();
+            let index = heading_texts.texts.len();
+            heading_texts.texts.push(String::new());
+            heading_texts.open.push(index);
+        }
+
+        let heading_texts = Rc::clone(heading_texts);
+        // text goes to the innermost open heading, so once a heading nested in this one ends,
+        // what follows is collected for this one again
+        let _ = element.on_end_tag(Box::new(move |_| {
+            heading_texts.borrow_mut().open.pop();
+            Ok(())
+        }));
+    }
+
+    /// A handler which gives each heading, in order, the id made from its text in `heading_texts`,
+    /// replacing any id it already had.
+    pub fn id_handlers<'a>(
+        &self,
+        heading_texts: &HeadingTexts,
+    ) -> Vec<(Cow<'a, Selector>, ElementContentHandlers<'a>)> {
+        let Some(id_prefix) = &self.id_prefix else {
+            return vec![];
+        };
+
+        let ids: Vec<String> = Self::slugs(&heading_texts.texts)
+            .into_iter()
+            .map(|slug| format!("{id_prefix}{slug}"))
+            .collect();
+        let mut ids = ids.into_iter();
+
+        vec![element!(HEADINGS_CSS, move |el| {
+            if let Some(id) = ids.next() {
+                el.set_attribute("id", &id)?;
+            }
+
+            Ok(())
+        })]
+    }
+
+    /// A slug for each of `texts`, made unique by adding `-1`, `-2` and so on to repeats.
+    fn slugs(texts: &[String]) -> Vec<String> {
+        let mut used = HashSet::new();
+
+        texts
+            .iter()
+            .map(|text| {
+                let slug = Self::slug(text);
+                let mut unique = slug.clone();
+                let mut suffix = 0;
+                while used.contains(&unique) {
+                    suffix += 1;
+                    unique = format!("{slug}-{suffix}");
+                }
+                used.insert(unique.clone());
+
+                unique
+            })
+            .collect()
+    }
+
+    /// `text` (still HTML-escaped) lowercased, with spaces turned into hyphens and anything
+    /// other than letters, digits, `-` and `_` left out.
+    fn slug(text: &str) -> String {
+        let text = if text.contains('&') {
+            Cow::Owned(
+                String::from_utf8_lossy(&escapist::unescape_html(text.as_bytes())).into_owned(),
+            )
+        } else {
+            Cow::Borrowed(text)
+        };
+
+        let slug: String = text
+            .trim()
+            .chars()
+            .flat_map(char::to_lowercase)
+            .filter_map(|c| match c {
+                ' ' => Some('-'),
+                '-' | '_' => Some(c),
+                c if c.is_alphanumeric() => Some(c),
+                _ => None,
+            })
+            .collect();
+
+        if slug.is_empty() {
+            EMPTY_SLUG.to_string()
+        } else {
+            slug
+        }
+    }
+}
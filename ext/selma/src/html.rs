@@ -6,6 +6,7 @@ pub fn init(m_selma: RModule) -> Result<(), Error> {
         .define_class("HTML", ruby.class_object())
         .expect("cannot define class Selma::HTML");
 
+    ancestor::init(c_html).expect("cannot define Selma::HTML::Ancestor class");
     element::init(c_html).expect("cannot define Selma::HTML::Element class");
     end_tag::init(c_html).expect("cannot define Selma::HTML::EndTag class");
     text_chunk::init(c_html).expect("cannot define Selma::HTML::TextChunk class");
@@ -13,6 +14,7 @@ pub fn init(m_selma: RModule) -> Result<(), Error> {
     Ok(())
 }
 
+pub mod ancestor;
 pub mod element;
 pub mod end_tag;
 pub mod text_chunk;
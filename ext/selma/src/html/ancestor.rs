@@ -0,0 +1,127 @@
+use crate::{ancestors::Ancestor, tags::Namespace};
+use magnus::{
+    method, typed_data::Obj, Error, Module, RArray, RClass, RHash, Ruby, TryConvert, Value,
+};
+
+/// A read-only copy of one of an element's ancestors, taken when its start tag was parsed.
+#[magnus::wrap(class = "Selma::HTML::Ancestor", free_immediately)]
+pub struct SelmaHTMLAncestor {
+    ancestor: Ancestor,
+    depth: usize,
+}
+
+impl SelmaHTMLAncestor {
+    pub fn new(ancestor: Ancestor, depth: usize) -> Self {
+        Self { ancestor, depth }
+    }
+
+    fn tag_name(&self) -> String {
+        self.ancestor.tag_name().to_string()
+    }
+
+    fn namespace_uri(&self) -> &'static str {
+        self.ancestor.namespace().uri()
+    }
+
+    fn is_svg(&self) -> bool {
+        self.ancestor.namespace() == Namespace::Svg
+    }
+
+    fn is_mathml(&self) -> bool {
+        self.ancestor.namespace() == Namespace::MathML
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn get_attribute(&self, attr: String) -> Option<String> {
+        self.ancestor.attribute(&attr).map(str::to_string)
+    }
+
+    fn has_attribute(&self, attr: String) -> bool {
+        self.ancestor.attribute(&attr).is_some()
+    }
+
+    fn get_attributes(&self) -> Result<RHash, Error> {
+        let ruby = Ruby::get().unwrap();
+        let hash = ruby.hash_new();
+
+        for (name, value) in self.ancestor.attributes() {
+            hash.aset(name.as_str(), value.as_str())?;
+        }
+
+        Ok(hash)
+    }
+
+    fn id(&self) -> Option<String> {
+        self.get_attribute("id".to_string())
+    }
+
+    fn classes(&self) -> RArray {
+        let ruby = Ruby::get().unwrap();
+        ruby.ary_from_iter(self.ancestor.classes())
+    }
+
+    fn has_class(&self, class: String) -> bool {
+        self.ancestor.classes().any(|c| c == class)
+    }
+
+    // ancestors used to be plain tag name strings; comparing equal to (and converting
+    // implicitly into) one keeps `element.ancestors.include?("pre")` and friends working
+    fn equals(&self, other: Value) -> bool {
+        match Obj::<Self>::try_convert(other) {
+            Ok(other) => self.depth == other.depth && self.ancestor == other.ancestor,
+            Err(_) => match String::try_convert(other) {
+                Ok(tag_name) => self.ancestor.tag_name() == tag_name,
+                Err(_) => false,
+            },
+        }
+    }
+
+    fn inspect(&self) -> String {
+        let attributes: String = self
+            .ancestor
+            .attributes()
+            .iter()
+            .map(|(name, value)| format!(" {name}={value:?}"))
+            .collect();
+
+        format!(
+            "#<Selma::HTML::Ancestor <{}{attributes}> depth={}>",
+            self.ancestor.tag_name(),
+            self.depth
+        )
+    }
+}
+
+pub fn init(c_html: RClass) -> Result<(), Error> {
+    let ruby = Ruby::get().unwrap();
+    let c_ancestor = c_html
+        .define_class("Ancestor", ruby.class_object())
+        .expect("cannot define class Selma::HTML::Ancestor");
+
+    c_ancestor.define_method("tag_name", method!(SelmaHTMLAncestor::tag_name, 0))?;
+    c_ancestor.define_method(
+        "namespace_uri",
+        method!(SelmaHTMLAncestor::namespace_uri, 0),
+    )?;
+    c_ancestor.define_method("svg?", method!(SelmaHTMLAncestor::is_svg, 0))?;
+    c_ancestor.define_method("mathml?", method!(SelmaHTMLAncestor::is_mathml, 0))?;
+    c_ancestor.define_method("depth", method!(SelmaHTMLAncestor::depth, 0))?;
+    c_ancestor.define_method("[]", method!(SelmaHTMLAncestor::get_attribute, 1))?;
+    c_ancestor.define_method(
+        "has_attribute?",
+        method!(SelmaHTMLAncestor::has_attribute, 1),
+    )?;
+    c_ancestor.define_method("attributes", method!(SelmaHTMLAncestor::get_attributes, 0))?;
+    c_ancestor.define_method("id", method!(SelmaHTMLAncestor::id, 0))?;
+    c_ancestor.define_method("classes", method!(SelmaHTMLAncestor::classes, 0))?;
+    c_ancestor.define_method("has_class?", method!(SelmaHTMLAncestor::has_class, 1))?;
+    c_ancestor.define_method("==", method!(SelmaHTMLAncestor::equals, 1))?;
+    c_ancestor.define_method("to_s", method!(SelmaHTMLAncestor::tag_name, 0))?;
+    c_ancestor.define_method("to_str", method!(SelmaHTMLAncestor::tag_name, 0))?;
+    c_ancestor.define_method("inspect", method!(SelmaHTMLAncestor::inspect, 0))?;
+
+    Ok(())
+}
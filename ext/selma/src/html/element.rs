@@ -1,12 +1,17 @@
 use std::cell::RefCell;
 
-use crate::native_ref_wrap::NativeRefWrap;
+use crate::{
+    ancestors::{Ancestor, AncestorSelector},
+    html::ancestor::SelmaHTMLAncestor,
+    native_ref_wrap::NativeRefWrap,
+    tags::Namespace,
+};
 use lol_html::html_content::Element;
 use magnus::{method, Error, Module, RArray, RClass, RHash, Ruby, Value};
 
 struct HTMLElement {
     element: NativeRefWrap<Element<'static, 'static>>,
-    ancestors: Vec<String>,
+    ancestors: Vec<Ancestor>,
 }
 
 #[magnus::wrap(class = "Selma::HTML::Element")]
@@ -16,7 +21,7 @@ pub struct SelmaHTMLElement(RefCell<HTMLElement>);
 unsafe impl Send for SelmaHTMLElement {}
 
 impl SelmaHTMLElement {
-    pub fn new(ref_wrap: NativeRefWrap<Element<'static, 'static>>, ancestors: &[String]) -> Self {
+    pub fn new(ref_wrap: NativeRefWrap<Element<'static, 'static>>, ancestors: &[Ancestor]) -> Self {
         Self(RefCell::new(HTMLElement {
             element: ref_wrap,
             ancestors: ancestors.to_owned(),
@@ -55,6 +60,30 @@ impl SelmaHTMLElement {
         }
     }
 
+    fn namespace(&self) -> Result<Namespace, Error> {
+        let binding = self.0.borrow();
+
+        match binding.element.get() {
+            Ok(e) => Ok(Namespace::of(e)),
+            Err(_) => Err(Error::new(
+                Ruby::get().unwrap().exception_runtime_error(),
+                "`namespace_uri` is not available",
+            )),
+        }
+    }
+
+    fn namespace_uri(&self) -> Result<&'static str, Error> {
+        Ok(self.namespace()?.uri())
+    }
+
+    fn is_svg(&self) -> Result<bool, Error> {
+        Ok(self.namespace()? == Namespace::Svg)
+    }
+
+    fn is_mathml(&self) -> Result<bool, Error> {
+        Ok(self.namespace()? == Namespace::MathML)
+    }
+
     fn is_self_closing(&self) -> Result<bool, Error> {
         let binding = self.0.borrow();
 
@@ -182,22 +211,40 @@ impl SelmaHTMLElement {
         let ruby = Ruby::get().unwrap();
         let array = ruby.ary_new();
 
-        binding
-            .ancestors
-            .iter()
-            .for_each(|ancestor| match array.push(ruby.str_new(ancestor)) {
-                Ok(_) => {}
-                Err(err) => {
-                    panic!(
-                        "{:?}",
-                        Error::new(ruby.exception_runtime_error(), format!("{err:?}"))
-                    )
-                }
-            });
+        for (depth, ancestor) in binding.ancestors.iter().enumerate() {
+            array.push(SelmaHTMLAncestor::new(ancestor.clone(), depth))?;
+        }
 
         Ok(array)
     }
 
+    fn parse_ancestor_selector(css: &str) -> Result<AncestorSelector, Error> {
+        AncestorSelector::parse(css).map_err(|err| {
+            Error::new(
+                Ruby::get().unwrap().exception_arg_error(),
+                format!("Could not parse `{css:?}`: {err}"),
+            )
+        })
+    }
+
+    fn is_inside(&self, css: String) -> Result<bool, Error> {
+        let selector = Self::parse_ancestor_selector(&css)?;
+
+        Ok(selector.matches_any(&self.0.borrow().ancestors))
+    }
+
+    fn closest(&self, css: String) -> Result<Option<SelmaHTMLAncestor>, Error> {
+        let selector = Self::parse_ancestor_selector(&css)?;
+        let ancestors = &self.0.borrow().ancestors;
+
+        Ok(selector
+            .states(ancestors)
+            .enumerate()
+            .filter(|(_, state)| selector.is_match(state))
+            .last()
+            .map(|(depth, _)| SelmaHTMLAncestor::new(ancestors[depth].clone(), depth)))
+    }
+
     fn before(&self, args: &[Value]) -> Result<(), Error> {
         let mut binding = self.0.borrow_mut();
         let element = binding.element.get_mut().unwrap();
@@ -307,6 +354,9 @@ pub fn init(c_html: RClass) -> Result<(), Error> {
 
     c_element.define_method("tag_name", method!(SelmaHTMLElement::tag_name, 0))?;
     c_element.define_method("tag_name=", method!(SelmaHTMLElement::set_tag_name, 1))?;
+    c_element.define_method("namespace_uri", method!(SelmaHTMLElement::namespace_uri, 0))?;
+    c_element.define_method("svg?", method!(SelmaHTMLElement::is_svg, 0))?;
+    c_element.define_method("mathml?", method!(SelmaHTMLElement::is_mathml, 0))?;
     c_element.define_method(
         "self_closing?",
         method!(SelmaHTMLElement::is_self_closing, 0),
@@ -327,6 +377,8 @@ pub fn init(c_html: RClass) -> Result<(), Error> {
         method!(SelmaHTMLElement::get_attribute_source_location, 1),
     )?;
     c_element.define_method("ancestors", method!(SelmaHTMLElement::get_ancestors, 0))?;
+    c_element.define_method("inside?", method!(SelmaHTMLElement::is_inside, 1))?;
+    c_element.define_method("closest", method!(SelmaHTMLElement::closest, 1))?;
 
     c_element.define_method("before", method!(SelmaHTMLElement::before, -1))?;
     c_element.define_method("after", method!(SelmaHTMLElement::after, -1))?;
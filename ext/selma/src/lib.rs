@@ -3,12 +3,21 @@ extern crate core;
 use lol_html::html_content::ContentType;
 use magnus::{scan_args, Error, Ruby, Symbol, Value};
 
+pub mod ancestors;
+pub mod clobber;
+pub mod headings;
 pub mod html;
+pub mod limits;
+pub mod link_policy;
 pub mod native_ref_wrap;
 pub mod rewriter;
 pub mod sanitizer;
+pub mod sanitizer_config;
 pub mod selector;
+pub mod stats;
 pub mod tags;
+pub mod url;
+pub mod url_rewriter;
 
 #[allow(clippy::let_unit_value)]
 fn scan_text_args(args: &[Value]) -> Result<(String, ContentType), magnus::Error> {
@@ -0,0 +1,381 @@
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    error::Error,
+    fmt,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use lol_html::{
+    doc_text, element,
+    html_content::{Comment, Element, TextChunk},
+    DocumentContentHandlers, ElementContentHandlers, HandlerResult, Selector,
+};
+use magnus::{
+    value::{Lazy, ReprValue},
+    ExceptionClass, Integer, Module, RClass, RHash, RModule, Ruby, Symbol, TryConvert, Value,
+};
+
+pub static LIMIT_EXCEEDED_ERROR: Lazy<ExceptionClass> = Lazy::new(|ruby| {
+    ruby.class_object()
+        .const_get::<_, RModule>("Selma")
+        .and_then(|m| m.const_get::<_, RClass>("Rewriter"))
+        .and_then(|c| c.const_get("LimitExceededError"))
+        .expect("cannot find Selma::Rewriter::LimitExceededError")
+});
+
+pub static TIMEOUT_ERROR: Lazy<ExceptionClass> = Lazy::new(|ruby| {
+    ruby.class_object()
+        .const_get::<_, RModule>("Selma")
+        .and_then(|m| m.const_get::<_, RClass>("Rewriter"))
+        .and_then(|c| c.const_get("TimeoutError"))
+        .expect("cannot find Selma::Rewriter::TimeoutError")
+});
+
+/// What to do once a document goes over one of its limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LimitBehavior {
+    /// Abort the rewrite with a `Selma::Rewriter::LimitExceededError`.
+    #[default]
+    Raise,
+    /// Stop emitting new content, but keep closing whatever is already open.
+    Truncate,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    pub max_output_bytes: Option<usize>,
+    pub max_element_depth: Option<usize>,
+    pub max_elements: Option<usize>,
+    pub on_limit: LimitBehavior,
+}
+
+impl Limits {
+    /// Whether no limit is set.
+    pub fn is_empty(&self) -> bool {
+        self.max_output_bytes.is_none()
+            && self.max_element_depth.is_none()
+            && self.max_elements.is_none()
+    }
+
+    /// Parses the `limits:` rewriter option.
+    pub fn from_hash(ruby: &Ruby, hash: RHash) -> Result<Self, magnus::Error> {
+        let on_limit = match hash.get(ruby.to_symbol("on_limit")) {
+            None => LimitBehavior::default(),
+            Some(value) => match Symbol::from_value(value).map(|s| s.name()) {
+                Some(Ok(name)) if name == "raise" => LimitBehavior::Raise,
+                Some(Ok(name)) if name == "truncate" => LimitBehavior::Truncate,
+                _ => {
+                    return Err(magnus::Error::new(
+                        ruby.exception_arg_error(),
+                        "on_limit must be `:raise` or `:truncate`",
+                    ));
+                }
+            },
+        };
+
+        Ok(Self {
+            max_output_bytes: Self::fetch_limit(ruby, hash, "max_output_bytes")?,
+            max_element_depth: Self::fetch_limit(ruby, hash, "max_element_depth")?,
+            max_elements: Self::fetch_limit(ruby, hash, "max_elements")?,
+            on_limit,
+        })
+    }
+
+    fn fetch_limit(ruby: &Ruby, hash: RHash, name: &str) -> Result<Option<usize>, magnus::Error> {
+        let Some(value) = hash.get(ruby.to_symbol(name)) else {
+            return Ok(None);
+        };
+        if value.is_nil() {
+            return Ok(None);
+        }
+
+        match Integer::from_value(value).map(|i| i.to_usize()) {
+            Some(Ok(limit)) if limit > 0 => Ok(Some(limit)),
+            _ => Err(magnus::Error::new(
+                ruby.exception_arg_error(),
+                format!("{name} must be a positive integer"),
+            )),
+        }
+    }
+}
+
+/// Parses the `timeout:` rewriter option, given in (fractional) seconds.
+pub fn timeout_from_value(ruby: &Ruby, value: Value) -> Result<Option<Duration>, magnus::Error> {
+    if value.is_nil() {
+        return Ok(None);
+    }
+
+    match f64::try_convert(value) {
+        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => {
+            Ok(Some(Duration::from_secs_f64(seconds)))
+        }
+        _ => Err(magnus::Error::new(
+            ruby.exception_arg_error(),
+            "timeout must be a positive number of seconds",
+        )),
+    }
+}
+
+/// Returned from a content handler to stop `lol_html` early.
+#[derive(Debug)]
+pub enum RewriteAborted {
+    /// A `limits:` option was exceeded in `:raise` mode.
+    LimitExceeded(String),
+    /// The `timeout:` option elapsed.
+    TimedOut(Duration),
+    /// Ruby raised into the rewriting thread (`Thread#raise`, signals, `Timeout`...);
+    /// the original exception is kept by the tracker.
+    Interrupted,
+    /// A `url_rewriter:` callable raised; the original exception is kept by `UrlRewriting`.
+    UrlRewriterRaised,
+}
+
+impl fmt::Display for RewriteAborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LimitExceeded(message) => f.write_str(message),
+            Self::TimedOut(timeout) => {
+                write!(f, "rewrite took longer than {}s", timeout.as_secs_f64())
+            }
+            Self::Interrupted => f.write_str("rewrite was interrupted"),
+            Self::UrlRewriterRaised => f.write_str("a `url_rewriter` raised"),
+        }
+    }
+}
+
+impl Error for RewriteAborted {}
+
+/// Per-rewrite counters for the `limits:` and `timeout:` options. The tracker's
+/// handlers are registered ahead of the sanitizer and every Ruby handler, so by
+/// the time anyone else sees an element it has already been counted (and, when
+/// truncating, removed).
+///
+/// Depths come from the rewrite's `AncestorStack`, rather than a stack of the
+/// tracker's own, so they follow the same implied end tags the handlers see.
+pub struct LimitTracker {
+    limits: Limits,
+    elements: Cell<usize>,
+    // what the current pass has written so far, and whether it went over `max_output_bytes`
+    output_bytes: Cell<usize>,
+    output_over: Cell<bool>,
+    truncated: Cell<bool>,
+    timeout: Option<Duration>,
+    started_at: Instant,
+    // whether Ruby handlers run during the rewrite
+    calls_ruby: bool,
+    interrupt: RefCell<Option<magnus::Error>>,
+}
+
+impl LimitTracker {
+    pub fn new(limits: Limits, timeout: Option<Duration>, calls_ruby: bool) -> Rc<Self> {
+        Rc::new(Self {
+            limits,
+            elements: Cell::new(0),
+            output_bytes: Cell::new(0),
+            output_over: Cell::new(false),
+            truncated: Cell::new(false),
+            timeout,
+            started_at: Instant::now(),
+            calls_ruby,
+            interrupt: RefCell::new(None),
+        })
+    }
+
+    /// Whether the tracker's handlers are needed at all: there's a limit or a
+    /// deadline to check, or Ruby handlers run during the rewrite, which a
+    /// pending interrupt should be able to stop. Otherwise they'd only cost
+    /// a handler call per element and text chunk.
+    pub fn is_needed(&self) -> bool {
+        !self.limits.is_empty() || self.timeout.is_some() || self.calls_ruby
+    }
+
+    /// Handlers which check the deadline and pending interrupts, for the passes
+    /// after the first one; limits are only counted in the first.
+    pub fn interrupt_check_handlers<'a>(
+        self: &Rc<Self>,
+    ) -> (
+        Vec<DocumentContentHandlers<'a>>,
+        Vec<(Cow<'a, Selector>, ElementContentHandlers<'a>)>,
+    ) {
+        if !self.is_needed() {
+            return (vec![], vec![]);
+        }
+
+        let element_tracker = Rc::clone(self);
+        let text_tracker = Rc::clone(self);
+
+        (
+            vec![doc_text!(move |_| text_tracker.check_interrupts())],
+            vec![element!("*", move |_| element_tracker.check_interrupts())],
+        )
+    }
+
+    /// Checks the deadline and lets Ruby run any pending interrupts. Called
+    /// between handler invocations, since nothing else gives Ruby a chance to
+    /// while `lol_html` is parsing.
+    pub fn check_interrupts(&self) -> HandlerResult {
+        if let Some(timeout) = self.timeout {
+            if self.started_at.elapsed() >= timeout {
+                return Err(Box::new(RewriteAborted::TimedOut(timeout)));
+            }
+        }
+
+        match Ruby::get().unwrap().thread_check_ints() {
+            Ok(()) => Ok(()),
+            Err(err) => Err(self.interrupt(err)),
+        }
+    }
+
+    /// Holds on to an exception Ruby raised mid-rewrite so that it can be
+    /// re-raised as-is once `lol_html` has unwound.
+    pub fn interrupt(&self, err: magnus::Error) -> Box<dyn Error + Send + Sync> {
+        self.interrupt.replace(Some(err));
+        Box::new(RewriteAborted::Interrupted)
+    }
+
+    pub fn take_interrupt(&self) -> Option<magnus::Error> {
+        self.interrupt.take()
+    }
+
+    /// Starts counting output over again, for a new pass over the document: the
+    /// limit is on what the last pass writes, not on what all of them add up to.
+    pub fn start_output(&self) {
+        self.output_bytes.set(0);
+        self.output_over.set(false);
+    }
+
+    /// Counts a chunk `lol_html` is about to write. Chunks are written some time
+    /// after the handlers have seen their content, so a chunk which takes the
+    /// output over the limit is noticed here, rather than by the next handler.
+    /// When truncating, that chunk is still written whole; cutting it would leave
+    /// half a tag behind, so `max_output_bytes` stops the output after it's
+    /// exceeded, not at it.
+    pub fn record_output(&self, len: usize) {
+        let written = self.output_bytes.get() + len;
+        self.output_bytes.set(written);
+
+        if self
+            .limits
+            .max_output_bytes
+            .is_some_and(|max| written > max)
+        {
+            self.output_over.set(true);
+        }
+    }
+
+    /// The error for a pass whose output went over `max_output_bytes`, once it's
+    /// done, when the rewrite should raise. Any handler that runs after the limit
+    /// is crossed raises it already; this covers the output written after the
+    /// last one, and every pass after the first.
+    pub fn output_overflow(&self) -> Option<RewriteAborted> {
+        if !self.output_over.get() || self.limits.on_limit != LimitBehavior::Raise {
+            return None;
+        }
+
+        let max = self.limits.max_output_bytes.unwrap_or_default();
+        Some(RewriteAborted::LimitExceeded(format!(
+            "max_output_bytes ({max}) exceeded"
+        )))
+    }
+
+    /// Whether content at `depth` (1 for the top-level elements, and for text
+    /// inside them) is being dropped; Ruby handlers aren't called for it.
+    pub fn suppressed(&self, depth: usize) -> bool {
+        self.truncated.get() || self.limits.max_element_depth.is_some_and(|max| depth > max)
+    }
+
+    /// Counts `element`, found at `depth`, against the limits.
+    pub fn handle_element(&self, element: &mut Element, depth: usize) -> HandlerResult {
+        self.check_interrupts()?;
+
+        if self.truncated.get() {
+            element.remove();
+            return Ok(());
+        }
+
+        let elements = self.elements.get() + 1;
+        self.elements.set(elements);
+        if let Some(max) = self.limits.max_elements {
+            if elements > max {
+                return self.limit_reached(format!("max_elements ({max}) exceeded"), || {
+                    element.remove()
+                });
+            }
+        }
+        if self.output_exceeded() {
+            return self.output_limit_reached(|| element.remove());
+        }
+
+        let Some(max) = self.limits.max_element_depth else {
+            return Ok(());
+        };
+
+        if depth > max {
+            match self.limits.on_limit {
+                LimitBehavior::Raise => {
+                    return Err(Box::new(RewriteAborted::LimitExceeded(format!(
+                        "max_element_depth ({max}) exceeded"
+                    ))));
+                }
+                // only this subtree is too deep; its siblings are still fine
+                LimitBehavior::Truncate => element.remove(),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_text(&self, text: &mut TextChunk, depth: usize) -> HandlerResult {
+        self.check_interrupts()?;
+
+        if self.suppressed(depth) {
+            text.remove();
+            return Ok(());
+        }
+        if self.output_exceeded() {
+            return self.output_limit_reached(|| text.remove());
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_comment(&self, comment: &mut Comment, depth: usize) -> HandlerResult {
+        if self.suppressed(depth) {
+            comment.remove();
+            return Ok(());
+        }
+        if self.output_exceeded() {
+            return self.output_limit_reached(|| comment.remove());
+        }
+
+        Ok(())
+    }
+
+    fn output_exceeded(&self) -> bool {
+        self.output_over.get()
+            || self
+                .limits
+                .max_output_bytes
+                .is_some_and(|max| self.output_bytes.get() >= max)
+    }
+
+    fn output_limit_reached(&self, remove: impl FnOnce()) -> HandlerResult {
+        let max = self.limits.max_output_bytes.unwrap_or_default();
+        self.limit_reached(format!("max_output_bytes ({max}) exceeded"), remove)
+    }
+
+    fn limit_reached(&self, message: String, remove: impl FnOnce()) -> HandlerResult {
+        match self.limits.on_limit {
+            LimitBehavior::Raise => Err(Box::new(RewriteAborted::LimitExceeded(message))),
+            LimitBehavior::Truncate => {
+                // everything from here on is dropped; end tags of elements
+                // that are already open still come through, closing them
+                self.truncated.set(true);
+                remove();
+                Ok(())
+            }
+        }
+    }
+}
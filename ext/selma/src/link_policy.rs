@@ -0,0 +1,146 @@
+use lol_html::{errors::AttributeNameError, html_content::Element};
+use magnus::{value::ReprValue, Error, RArray, RHash, RString, Ruby, Value};
+
+use crate::sanitizer_config::invalid;
+
+const DEFAULT_REL: &[&str] = &["nofollow", "ugc", "noopener"];
+
+/// A host in `internal_hosts`: `example.com` matches only itself, while `*.example.com` matches
+/// any of its subdomains.
+#[derive(Debug)]
+enum HostPattern {
+    Exact(String),
+    Subdomains(String),
+}
+
+impl HostPattern {
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Exact(pattern) => host == pattern,
+            HostPattern::Subdomains(domain) => host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+        }
+    }
+}
+
+/// `link_policy:`, compiled: what to add to links that lead away from the `internal_hosts`.
+#[derive(Debug)]
+pub struct LinkPolicy {
+    internal_hosts: Vec<HostPattern>,
+    rel: Vec<String>,
+    target: Option<String>,
+}
+
+impl LinkPolicy {
+    pub fn from_config(config: RHash) -> Result<Option<Self>, Error> {
+        let ruby = Ruby::get().unwrap();
+        let Some(link_policy) = config.get(ruby.to_symbol("link_policy")) else {
+            return Ok(None);
+        };
+        if link_policy.is_nil() {
+            return Ok(None);
+        }
+        let Some(link_policy) = RHash::from_value(link_policy) else {
+            return Err(invalid(format!(
+                "`link_policy` must be a Hash, not `{}`",
+                link_policy.inspect()
+            )));
+        };
+
+        let internal_hosts = Self::strings(link_policy, "internal_hosts")?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|host| {
+                let host = host.trim_end_matches('.').to_ascii_lowercase();
+                match host.strip_prefix("*.") {
+                    Some(domain) => HostPattern::Subdomains(domain.to_string()),
+                    None => HostPattern::Exact(host),
+                }
+            })
+            .collect();
+        let rel = Self::strings(link_policy, "rel")?
+            .unwrap_or_else(|| DEFAULT_REL.iter().map(|rel| rel.to_string()).collect());
+        let target = match link_policy.get(ruby.to_symbol("target")) {
+            None => None,
+            Some(target) if target.is_nil() => None,
+            Some(target) => match RString::from_value(target) {
+                Some(target) => Some(target.to_string()?),
+                None => {
+                    return Err(invalid(format!(
+                        "`link_policy[:target]` must be a String, like \"_blank\", not `{}`",
+                        target.inspect()
+                    )))
+                }
+            },
+        };
+
+        Ok(Some(LinkPolicy {
+            internal_hosts,
+            rel,
+            target,
+        }))
+    }
+
+    // `link_policy[key]`, as an Array of Strings
+    fn strings(link_policy: RHash, key: &str) -> Result<Option<Vec<String>>, Error> {
+        let ruby = Ruby::get().unwrap();
+        let Some(value) = link_policy.get(ruby.to_symbol(key)) else {
+            return Ok(None);
+        };
+        let invalid_value = || {
+            invalid(format!(
+                "`link_policy[:{key}]` must be an Array of Strings, not `{}`",
+                value.inspect()
+            ))
+        };
+
+        let array = RArray::from_value(value).ok_or_else(invalid_value)?;
+        array
+            .into_iter()
+            .map(|item: Value| {
+                RString::from_value(item)
+                    .ok_or_else(invalid_value)?
+                    .to_string()
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    /// Whether `href`, which has passed its protocol check, leads to a host that isn't internal.
+    /// Relative URLs stay on the same site, and URLs without a host (like `mailto:`) aren't links
+    /// to another site. An `https:` (or similar) URL whose host can't be made out might still
+    /// lead anywhere, though, so it counts as external.
+    pub fn is_external(&self, href: &str) -> bool {
+        match crate::url::host(href) {
+            Some(host) => !self
+                .internal_hosts
+                .iter()
+                .any(|pattern| pattern.matches(&host)),
+            None => crate::url::has_host_scheme(href),
+        }
+    }
+
+    /// Adds the policy's `rel` values to the link, keeping any it already has, and sets its `target`.
+    pub fn apply(&self, element: &mut Element) -> Result<(), AttributeNameError> {
+        if !self.rel.is_empty() {
+            let existing = element.get_attribute("rel").unwrap_or_default();
+            let mut rel: Vec<&str> = existing.split_ascii_whitespace().collect();
+            for value in &self.rel {
+                if !rel
+                    .iter()
+                    .any(|existing| existing.eq_ignore_ascii_case(value))
+                {
+                    rel.push(value);
+                }
+            }
+            element.set_attribute("rel", &rel.join(" "))?;
+        }
+
+        if let Some(target) = &self.target {
+            element.set_attribute("target", target)?;
+        }
+
+        Ok(())
+    }
+}
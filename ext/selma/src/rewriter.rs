@@ -1,5 +1,6 @@
 use lol_html::{
-    doc_comments, doctype, element,
+    doc_comments, doc_text, element,
+    errors::RewritingError,
     html_content::{Element, TextChunk},
     text, DocumentContentHandlers, ElementContentHandlers, HtmlRewriter, MemorySettings, Selector,
     Settings,
@@ -11,24 +12,31 @@ use magnus::{
     typed_data::Obj,
     value::{Opaque, ReprValue},
     DataTypeFunctions, Integer, IntoValue, Module, Object, RArray, RHash, RModule, Ruby, Symbol,
-    TypedData, Value,
+    TryConvert, TypedData, Value,
 };
 
 use std::{
     borrow::Cow,
-    cell::{Ref, RefCell},
+    cell::{Cell, Ref},
     mem,
     ops::Deref,
     primitive::str,
     rc::Rc,
+    time::{Duration, Instant},
 };
 
 use crate::{
+    ancestors::{Ancestor, AncestorStack, TrackedSelector},
     html::{element::SelmaHTMLElement, end_tag::SelmaHTMLEndTag, text_chunk::SelmaHTMLTextChunk},
+    limits::{
+        timeout_from_value, LimitTracker, Limits, RewriteAborted, LIMIT_EXCEEDED_ERROR,
+        TIMEOUT_ERROR,
+    },
     native_ref_wrap::NativeRefWrap,
     sanitizer::SelmaSanitizer,
     selector::SelmaSelector,
-    tags::Tag,
+    stats::{self, Timing},
+    url_rewriter::UrlRewriting,
 };
 
 #[derive(Clone)]
@@ -39,23 +47,28 @@ pub struct Handler {
     // `Obj`/`Opaque` handle to it would make its lifetime depend on GC (use-after-free if it is
     // collected while the Rewriter is alive). We only ever read Rust data off it, so clone it.
     selector: SelmaSelector,
-    // total_element_handler_calls: usize,
-    // total_elapsed_element_handlers: f64,
+    // whether `handle_element` takes a second argument, for the selector which matched
+    wants_matched_selector: bool,
 
-    // total_text_handler_calls: usize,
-    // total_elapsed_text_handlers: f64,
+    element_handler_timing: Timing,
+    text_handler_timing: Timing,
 }
 
 struct RewriterOptions {
     max_allowed_memory_usage: usize,
     preallocated_parsing_buffer_size: usize,
+    limits: Limits,
+    timeout: Option<Duration>,
 }
 
 pub struct Rewriter {
     sanitizer: Option<SelmaSanitizer>,
     handlers: Vec<Handler>,
     options: RewriterOptions,
-    // total_elapsed: f64,
+
+    rewrite_timing: Timing,
+    sanitization_timing: Timing,
+    final_sanitization_timing: Timing,
 }
 
 #[derive(TypedData)]
@@ -64,9 +77,15 @@ pub struct SelmaRewriter(std::cell::RefCell<Rewriter>);
 
 impl DataTypeFunctions for SelmaRewriter {
     fn mark(&self, marker: &gc::Marker) {
-        self.0.borrow().handlers.iter().for_each(|handler| {
+        let binding = self.0.borrow();
+        binding.handlers.iter().for_each(|handler| {
             marker.mark(handler.rb_handler);
         });
+        // the rewriter keeps its own copy of the sanitizer, which has to keep the config and
+        // `url_rewriter:` callables alive once the `Selma::Sanitizer` it came from is gone
+        if let Some(sanitizer) = &binding.sanitizer {
+            sanitizer.mark(marker);
+        }
     }
 }
 
@@ -134,11 +153,10 @@ impl SelmaRewriter {
                         // clone the selector's data out of the Ruby object right away so the
                         // Handler no longer depends on that object surviving GC (see struct docs)
                         selector: (*rb_selector).clone(),
-                        // total_element_handler_calls: 0,
-                        // total_elapsed_element_handlers: 0.0,
+                        wants_matched_selector: Self::wants_matched_selector(rb_handler)?,
 
-                        // total_text_handler_calls: 0,
-                        // total_elapsed_text_handlers: 0.0,
+                        element_handler_timing: Timing::default(),
+                        text_handler_timing: Timing::default(),
                     };
                     handlers.push(handler);
                 }
@@ -158,11 +176,12 @@ impl SelmaRewriter {
         match rb_options {
             None => {}
             Some(options) => {
-                options.foreach(|key: Symbol, value: RHash| {
+                options.foreach(|key: Symbol, value: Value| {
                     let ruby = Ruby::get().unwrap();
                     let key = key.to_string();
                     match key.as_str() {
                         "memory" => {
+                            let value = RHash::try_convert(value)?;
                             if let Some(max_allowed_memory_usage) = value.get(ruby.to_symbol("max_allowed_memory_usage")) {
                                 let max_allowed_memory_usage =
                                     Integer::from_value(max_allowed_memory_usage);
@@ -205,6 +224,13 @@ impl SelmaRewriter {
                                 }
                             }
                         }
+                        "limits" => {
+                            rewriter_options.limits =
+                                Limits::from_hash(ruby, RHash::try_convert(value)?)?;
+                        }
+                        "timeout" => {
+                            rewriter_options.timeout = timeout_from_value(ruby, value)?;
+                        }
                         _ => {
                             return Err(magnus::Error::new(
                                 ruby.exception_arg_error(),
@@ -230,10 +256,40 @@ impl SelmaRewriter {
             sanitizer,
             handlers,
             options: rewriter_options,
-            // total_elapsed: 0.0,
+
+            rewrite_timing: Timing::default(),
+            sanitization_timing: Timing::default(),
+            final_sanitization_timing: Timing::default(),
         })))
     }
 
+    /// `handle_element(element)` keeps working as-is; `handle_element(element, matched_selector)`
+    /// (or any other signature that takes a second positional argument) also gets the matched
+    /// selector. This goes by the method's `parameters` rather than its `arity`, which can't tell
+    /// `(element, *rest)` from `(element, **options)` or `(element, key: nil)`.
+    fn wants_matched_selector(rb_handler: Value) -> Result<bool, magnus::Error> {
+        if !rb_handler.respond_to(Self::SELMA_HANDLE_ELEMENT, true)? {
+            return Ok(false);
+        }
+
+        let method: Value = rb_handler.funcall("method", (Self::SELMA_HANDLE_ELEMENT,))?;
+        let parameters: RArray = method.funcall("parameters", ())?;
+
+        let mut required = 0;
+        let mut optional = 0;
+        let mut rest = false;
+        for parameter in parameters.to_vec::<RArray>()? {
+            match parameter.entry::<Symbol>(0)?.name()?.as_ref() {
+                "req" => required += 1,
+                "opt" => optional += 1,
+                "rest" => rest = true,
+                _ => {}
+            }
+        }
+
+        Ok(required <= 2 && (required + optional >= 2 || rest))
+    }
+
     #[allow(clippy::let_unit_value)]
     fn scan_parse_args(args: &[Value]) -> Result<RewriterValues, magnus::Error> {
         let args = scan_args::scan_args(args)?;
@@ -261,47 +317,33 @@ impl SelmaRewriter {
     /// Perform HTML rewrite sequence.
     fn rewrite(&self, html: String) -> Result<String, magnus::Error> {
         let binding = self.0.borrow();
-
-        let mut sanitizer_document_content_handlers: Vec<DocumentContentHandlers> = vec![];
-        let mut sanitizer_element_content_handlers: Vec<(Cow<Selector>, ElementContentHandlers)> =
-            vec![];
-
-        match &binding.sanitizer {
-            None => (),
-            Some(sanitizer) => {
-                if !sanitizer.get_allow_doctype() {
-                    sanitizer_document_content_handlers.push(doctype!(|d| {
-                        sanitizer.remove_doctype(d);
-                        Ok(())
-                    }));
-                }
-                if !sanitizer.get_allow_comments() {
-                    sanitizer_document_content_handlers.push(doc_comments!(|c| {
-                        sanitizer.remove_comment(c);
-                        Ok(())
-                    }));
+        let started_at = Instant::now();
+        let sanitization_timing = &binding.sanitization_timing;
+        let input_bytes = html.len();
+        let elements_removed = Cell::new(0_usize);
+
+        let limit_tracker = LimitTracker::new(
+            binding.options.limits,
+            binding.options.timeout,
+            !binding.handlers.is_empty(),
+        );
+
+        let (sanitizer_document_content_handlers, sanitizer_element_content_handlers) =
+            match &binding.sanitizer {
+                None => (vec![], vec![]),
+                Some(sanitizer) => {
+                    sanitizer.sanitization_handlers(sanitization_timing, &elements_removed)
                 }
-                sanitizer_element_content_handlers.push(element!("*", |el| {
-                    sanitizer.try_remove_element(el);
-                    if el.removed() {
-                        return Ok(());
-                    }
-                    // if it was removed, there are no attributes to sanitize
-                    match sanitizer.sanitize_attributes(el) {
-                        Ok(_) => Ok(()),
-                        Err(err) => Err(err.to_string().into()),
-                    }
-                }));
-            }
-        };
+            };
 
         let handlers: &Vec<Handler> = &binding.handlers;
 
-        match Self::perform_handler_rewrite(
+        let result = match Self::perform_handler_rewrite(
             self,
             sanitizer_document_content_handlers,
             sanitizer_element_content_handlers,
             handlers,
+            Rc::clone(&limit_tracker),
             html,
         ) {
             Ok(rewritten_html) => match &binding.sanitizer {
@@ -312,36 +354,127 @@ impl SelmaRewriter {
                         format!("{err:?}"),
                     )),
                 },
-                Some(sanitizer) => {
-                    Self::perform_final_sanitization(self, sanitizer, rewritten_html)
-                }
+                Some(sanitizer) => binding.final_sanitization_timing.time(|| {
+                    Self::perform_final_sanitization(
+                        self,
+                        sanitizer,
+                        &limit_tracker,
+                        &elements_removed,
+                        rewritten_html,
+                    )
+                }),
             },
             Err(err) => Err(err),
+        };
+
+        let elapsed = started_at.elapsed();
+        binding.rewrite_timing.record(elapsed);
+
+        match result {
+            // nothing gets reported for an interrupted rewrite; the interrupt itself takes priority
+            Err(err) if Self::is_interrupt(&err) => Err(err),
+            result => {
+                Self::instrument_rewrite(&result, input_bytes, elements_removed.get(), elapsed)?;
+                result
+            }
         }
     }
 
+    /// Sends a `rewrite.selma` event to `Selma.instrument`, when a callback is registered.
+    /// A callback which raises on a failed rewrite doesn't hide the original error.
+    fn instrument_rewrite(
+        result: &Result<String, magnus::Error>,
+        input_bytes: usize,
+        elements_removed: usize,
+        elapsed: Duration,
+    ) -> Result<(), magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+        let Some(callback) = stats::instrumenter(&ruby)? else {
+            return Ok(());
+        };
+
+        let payload = ruby.hash_new();
+        payload.aset(ruby.to_symbol("input_bytes"), input_bytes)?;
+        payload.aset(
+            ruby.to_symbol("output_bytes"),
+            result.as_ref().ok().map(|output| output.len()),
+        )?;
+        payload.aset(ruby.to_symbol("elements_removed"), elements_removed)?;
+        payload.aset(ruby.to_symbol("duration"), elapsed.as_secs_f64())?;
+
+        match result {
+            Ok(_) => stats::instrument(callback, "rewrite.selma", payload),
+            Err(err) => {
+                payload.aset(ruby.to_symbol("exception"), err.value())?;
+                let _ = stats::instrument(callback, "rewrite.selma", payload);
+                Ok(())
+            }
+        }
+    }
+
+    /// @yard
+    /// @def stats
+    /// Call counts and cumulative time (in seconds) spent by this rewriter, across every `#rewrite`.
+    /// @return [Hash]
+    fn stats(&self) -> Result<RHash, magnus::Error> {
+        let binding = self.0.borrow();
+        let ruby = Ruby::get().unwrap();
+
+        let handlers = ruby.ary_new();
+        for handler in binding.handlers.iter() {
+            let handler_stats = ruby.hash_new();
+            handler_stats.aset(
+                ruby.to_symbol("handler"),
+                handler.rb_handler.into_value_with(&ruby),
+            )?;
+            handler_stats.aset(
+                ruby.to_symbol("handle_element"),
+                handler.element_handler_timing.to_hash(&ruby)?,
+            )?;
+            handler_stats.aset(
+                ruby.to_symbol("handle_text_chunk"),
+                handler.text_handler_timing.to_hash(&ruby)?,
+            )?;
+            handlers.push(handler_stats)?;
+        }
+
+        let stats = ruby.hash_new();
+        stats.aset(ruby.to_symbol("rewrites"), binding.rewrite_timing.calls())?;
+        stats.aset(
+            ruby.to_symbol("elapsed"),
+            binding.rewrite_timing.elapsed().as_secs_f64(),
+        )?;
+        stats.aset(
+            ruby.to_symbol("sanitization"),
+            binding.sanitization_timing.to_hash(&ruby)?,
+        )?;
+        stats.aset(
+            ruby.to_symbol("final_sanitization"),
+            binding.final_sanitization_timing.to_hash(&ruby)?,
+        )?;
+        stats.aset(ruby.to_symbol("handlers"), handlers)?;
+
+        Ok(stats)
+    }
+
     // to get rid of some really nasty edge cases with dangerous tags, we perform one more
     // sanitization pass at the end
     fn perform_final_sanitization(
         &self,
         sanitizer: &SelmaSanitizer,
+        limit_tracker: &Rc<LimitTracker>,
+        elements_removed: &Cell<usize>,
         html: Vec<u8>,
     ) -> Result<String, magnus::Error> {
         // TODO: this should ideally be done ahead of time on `initialize`, not on every `#rewrite` call
-        let mut element_content_handlers: Vec<(Cow<Selector>, ElementContentHandlers)> = vec![];
-
-        if sanitizer.get_escape_tagfilter() {
-            element_content_handlers.push(element!(Tag::ESCAPEWORTHY_TAGS_CSS, |el| {
-                let should_remove = sanitizer.allow_element(el);
-                if should_remove {
-                    sanitizer.force_remove_element(el);
-                }
-
-                Ok(())
-            }));
-        }
-
-        match Self::run_rewrite(self, vec![], element_content_handlers, html.as_slice()) {
+        let rewritten_html = sanitizer.run_final_passes(
+            &|| self.memory_settings(),
+            Some(limit_tracker),
+            elements_removed,
+            html.as_slice(),
+        );
+
+        match rewritten_html {
             Ok(rewritten_html) => match String::from_utf8(rewritten_html) {
                 Ok(output) => Ok(output),
                 Err(err) => Err(magnus::Error::new(
@@ -355,101 +488,175 @@ impl SelmaRewriter {
 
     pub fn perform_handler_rewrite<'a>(
         &self,
-        sanitizer_document_content_handlers: Vec<DocumentContentHandlers<'a>>,
+        mut sanitizer_document_content_handlers: Vec<DocumentContentHandlers<'a>>,
         sanitizer_element_content_handlers: Vec<(Cow<Selector>, ElementContentHandlers<'a>)>,
         handlers: &'a [Handler],
+        limit_tracker: Rc<LimitTracker>,
         html: String,
     ) -> Result<Vec<u8>, magnus::Error> {
         // TODO: this should ideally be done ahead of time on `initialize`, not on every `#rewrite` call
         let mut element_content_handlers: Vec<(Cow<Selector>, ElementContentHandlers)> = vec![];
 
+        // the selectors which would otherwise be checked by walking back through the stack are
+        // tracked by it instead, as each element is pushed
+        let mut ancestor_stack = AncestorStack::default();
+        let tracked_selectors: Vec<(Vec<Option<TrackedSelector>>, Option<TrackedSelector>)> =
+            handlers
+                .iter()
+                .map(|handler| {
+                    let selector = &handler.selector;
+                    let match_element = selector
+                        .match_element()
+                        .iter()
+                        .map(|match_element| {
+                            match_element
+                                .namespaced()
+                                .map(|namespaced| ancestor_stack.track(namespaced.clone()))
+                        })
+                        .collect();
+                    let ignore_text_within = selector
+                        .ignore_text_within()
+                        .map(|ignore_text_within| ancestor_stack.track(ignore_text_within.clone()));
+
+                    (match_element, ignore_text_within)
+                })
+                .collect();
+        let ancestor_stack = Rc::new(ancestor_stack);
+        // limits on depth are measured against the same stack the handlers see
+        let tracks_ancestors =
+            !handlers.is_empty() || self.0.borrow().options.limits.max_element_depth.is_some();
+
+        // implied end tags are applied, and limits (and pending interrupts) are checked, before
+        // anything else gets a chance to see the content
+        let checks_limits = limit_tracker.is_needed();
+        if tracks_ancestors || checks_limits {
+            let element_tracker = Rc::clone(&limit_tracker);
+            let element_stack = Rc::clone(&ancestor_stack);
+            element_content_handlers.push(element!("*", move |el| {
+                if tracks_ancestors {
+                    element_stack.close_implied_by(el);
+                }
+                if !checks_limits {
+                    return Ok(());
+                }
+                element_tracker.handle_element(el, element_stack.depth() + 1)
+            }));
+        }
+        if checks_limits {
+            let text_tracker = Rc::clone(&limit_tracker);
+            let text_stack = Rc::clone(&ancestor_stack);
+            sanitizer_document_content_handlers.push(doc_text!(move |t| {
+                text_tracker.handle_text(t, text_stack.depth())
+            }));
+            let comment_tracker = Rc::clone(&limit_tracker);
+            let comment_stack = Rc::clone(&ancestor_stack);
+            sanitizer_document_content_handlers.push(doc_comments!(move |c| {
+                comment_tracker.handle_comment(c, comment_stack.depth())
+            }));
+        }
+
         // have sanitization happen first
         element_content_handlers.extend(sanitizer_element_content_handlers);
 
-        handlers.iter().for_each(|handler| {
-            let element_stack: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(vec![]));
-
+        for (handler, (tracked_match_element, tracked_ignore_text_within)) in
+            handlers.iter().zip(tracked_selectors)
+        {
             let selector = &handler.selector;
 
             // TODO: test final raise by simulating errors
-            if let Some(match_element) = selector.match_element() {
-                let closure_element_stack = element_stack.clone();
-
-                element_content_handlers.push(element!(match_element, move |el| {
-                    match Self::process_element_handlers(
-                        handler,
-                        el,
-                        &closure_element_stack.borrow(),
-                    ) {
+            for (match_element, namespaced) in
+                selector.match_element().iter().zip(tracked_match_element)
+            {
+                let closure_ancestor_stack = ancestor_stack.clone();
+                let closure_limit_tracker = limit_tracker.clone();
+
+                element_content_handlers.push(element!(match_element.lol_html_css(), move |el| {
+                    if closure_limit_tracker.suppressed(closure_ancestor_stack.depth() + 1) {
+                        return Ok(());
+                    }
+
+                    // `lol_html` ignored the namespace, so check it here
+                    if namespaced
+                        .is_some_and(|namespaced| !closure_ancestor_stack.matches(namespaced, el))
+                    {
+                        return Ok(());
+                    }
+
+                    let ancestors = closure_ancestor_stack.borrow();
+                    match handler.element_handler_timing.time(|| {
+                        Self::process_element_handlers(handler, el, match_element.css(), &ancestors)
+                    }) {
                         Ok(_) => Ok(()),
+                        Err(err) if Self::is_interrupt(&err) => {
+                            Err(closure_limit_tracker.interrupt(err))
+                        }
                         Err(err) => Err(err.to_string().into()),
                     }
                 }));
             }
 
-            if let Some(match_text_within) = selector.match_text_within() {
-                let closure_element_stack = element_stack.clone();
+            // unlike elements, text isn't told which selector matched it, so a single selector
+            // list keeps a chunk matching several of them from being handled more than once
+            if !selector.match_text_within().is_empty() {
+                let match_text_within = selector.match_text_within().join(", ");
+                let closure_ancestor_stack = ancestor_stack.clone();
+                let closure_limit_tracker = limit_tracker.clone();
 
                 element_content_handlers.push(text!(match_text_within, move |text| {
-                    let element_stack = closure_element_stack.as_ref().borrow();
+                    if closure_limit_tracker.suppressed(closure_ancestor_stack.depth()) {
+                        return Ok(());
+                    }
+
                     // check if current tag is a tag we should be ignoring text within;
                     // also checks if tag is within an ancestery of ignored tags
-                    if let Some(ignore_text_within) = selector.ignore_text_within() {
-                        if ignore_text_within.iter().any(|t| element_stack.contains(t)) {
-                            return Ok(());
-                        }
+                    if tracked_ignore_text_within.is_some_and(|ignore_text_within| {
+                        closure_ancestor_stack.is_within(ignore_text_within)
+                    }) {
+                        return Ok(());
                     }
 
-                    match Self::process_text_handlers(handler, text) {
+                    match handler
+                        .text_handler_timing
+                        .time(|| Self::process_text_handlers(handler, text))
+                    {
                         Ok(_) => Ok(()),
+                        Err(err) if Self::is_interrupt(&err) => {
+                            Err(closure_limit_tracker.interrupt(err))
+                        }
                         Err(err) => Err(err.to_string().into()),
                     }
                 }));
             }
+        }
 
-            // we need to check *every* element we iterate over, to create a stack of elements
-            element_content_handlers.push(element!("*", move |el| {
-                let tag_name = el.tag_name().to_lowercase();
-
-                // no need to track self-closing tags
-                if Tag::tag_from_tag_name(&tag_name).self_closing {
-                    return Ok(());
-                };
-
-                element_stack.as_ref().borrow_mut().push(tag_name);
-
-                let closure_element_stack = element_stack.clone();
-
-                let handler: lol_html::EndTagHandler<'static> = Box::new(move |_end_tag| {
-                    closure_element_stack.as_ref().borrow_mut().pop();
-                    Ok(())
-                });
-                // ignore void elements (lol_html's void list may differ from selma's `self_closing`)
-                let _ = el.on_end_tag(handler);
-
-                Ok(())
-            }));
-        });
+        // we need to check *every* element we iterate over, to create a stack of elements; this
+        // goes last, so that handlers only ever see an element's ancestors, not the element itself
+        if tracks_ancestors {
+            element_content_handlers.push(element!("*", move |el| ancestor_stack.push(el)));
+        }
 
-        Self::run_rewrite(
-            self,
+        Self::run_pass(
+            self.memory_settings(),
             sanitizer_document_content_handlers,
             element_content_handlers,
+            Some(limit_tracker.as_ref()),
             html.as_bytes(),
         )
     }
 
-    fn run_rewrite<'a>(
-        &self,
+    /// Runs `lol_html` over `html` once, with the given handlers. Every pass, whether it's part
+    /// of `Selma::Rewriter#rewrite` or `Selma::Sanitizer#sanitize`, goes through here, so that
+    /// limits, timeouts and interrupts are handled the same way in all of them.
+    pub fn run_pass<'a>(
+        memory_settings: MemorySettings,
         document_content_handlers: Vec<DocumentContentHandlers<'a>>,
         element_content_handlers: Vec<(Cow<Selector>, ElementContentHandlers<'a>)>,
+        limit_tracker: Option<&LimitTracker>,
         html: &[u8],
     ) -> Result<Vec<u8>, magnus::Error> {
-        let binding = &self.0.borrow();
         let mut output = vec![];
         {
-            let mut settings =
-                Settings::new().with_memory_settings(Self::get_memory_options(binding));
+            let mut settings = Settings::new().with_memory_settings(memory_settings);
             for handler in document_content_handlers {
                 settings = settings.append_document_content_handler(handler);
             }
@@ -457,24 +664,73 @@ impl SelmaRewriter {
                 settings = settings.append_element_content_handler(handler);
             }
 
-            let mut rewriter = HtmlRewriter::new(settings, |c: &[u8]| output.extend_from_slice(c));
+            if let Some(tracker) = limit_tracker {
+                tracker.start_output();
+            }
+            let mut rewriter = HtmlRewriter::new(settings, |c: &[u8]| {
+                if let Some(tracker) = limit_tracker {
+                    tracker.record_output(c.len());
+                }
+                output.extend_from_slice(c)
+            });
             match rewriter.write(html) {
                 Ok(_) => {}
                 Err(err) => {
-                    return Err(magnus::Error::new(
-                        Ruby::get().unwrap().exception_runtime_error(),
-                        format!("{err:?}"),
-                    ));
+                    return Err(Self::rewriting_error(err, limit_tracker));
                 }
             }
         }
+        if let Some(aborted) = limit_tracker.and_then(LimitTracker::output_overflow) {
+            return Err(magnus::Error::new(
+                Ruby::get().unwrap().get_inner(&LIMIT_EXCEEDED_ERROR),
+                aborted.to_string(),
+            ));
+        }
         Ok(output)
     }
 
+    fn rewriting_error(err: RewritingError, limit_tracker: Option<&LimitTracker>) -> magnus::Error {
+        let ruby = Ruby::get().unwrap();
+
+        if let RewritingError::ContentHandlerError(handler_err) = &err {
+            match handler_err.downcast_ref::<RewriteAborted>() {
+                Some(aborted @ RewriteAborted::LimitExceeded(_)) => {
+                    return magnus::Error::new(
+                        ruby.get_inner(&LIMIT_EXCEEDED_ERROR),
+                        aborted.to_string(),
+                    );
+                }
+                Some(aborted @ RewriteAborted::TimedOut(_)) => {
+                    return magnus::Error::new(ruby.get_inner(&TIMEOUT_ERROR), aborted.to_string());
+                }
+                Some(RewriteAborted::Interrupted) => {
+                    if let Some(interrupt) = limit_tracker.and_then(|t| t.take_interrupt()) {
+                        return interrupt;
+                    }
+                }
+                Some(RewriteAborted::UrlRewriterRaised) => {
+                    if let Some(raised) = UrlRewriting::take_raised() {
+                        return raised;
+                    }
+                }
+                None => {}
+            }
+        }
+
+        magnus::Error::new(ruby.exception_runtime_error(), format!("{err:?}"))
+    }
+
+    // exceptions that aren't `StandardError`s (`Interrupt`, `Timeout::ExitException`, `throw`...)
+    // must reach the caller untouched, rather than being flattened into a `RuntimeError`
+    fn is_interrupt(err: &magnus::Error) -> bool {
+        !err.is_kind_of(Ruby::get().unwrap().exception_standard_error())
+    }
+
     fn process_element_handlers(
         handler: &Handler,
         element: &mut Element,
-        ancestors: &[String],
+        matched_selector: &str,
+        ancestors: &[Ancestor],
     ) -> Result<(), magnus::Error> {
         let ruby = Ruby::get().unwrap();
         let rb_handler = handler.rb_handler.into_value_with(&ruby);
@@ -507,12 +763,18 @@ impl SelmaRewriter {
 
         let (ref_wrap, anchor) = NativeRefWrap::wrap(element);
         let rb_element = SelmaHTMLElement::new(ref_wrap, ancestors);
-        let result = rb_handler.funcall::<_, _, Value>(Self::SELMA_HANDLE_ELEMENT, (rb_element,));
+        let result = if handler.wants_matched_selector {
+            rb_handler
+                .funcall::<_, _, Value>(Self::SELMA_HANDLE_ELEMENT, (rb_element, matched_selector))
+        } else {
+            rb_handler.funcall::<_, _, Value>(Self::SELMA_HANDLE_ELEMENT, (rb_element,))
+        };
 
         mem::drop(anchor);
 
         match result {
             Ok(_) => Ok(()),
+            Err(err) if Self::is_interrupt(&err) => Err(err),
             Err(err) => Err(magnus::Error::new(
                 ruby.exception_runtime_error(),
                 format!("{err:?}"),
@@ -546,6 +808,7 @@ impl SelmaRewriter {
 
         match result {
             Ok(_) => Ok(()),
+            Err(err) if Self::is_interrupt(&err) => Err(err),
             Err(err) => Err(magnus::Error::new(
                 ruby.exception_runtime_error(),
                 format!("{err:?}"),
@@ -553,6 +816,10 @@ impl SelmaRewriter {
         }
     }
 
+    fn memory_settings(&self) -> MemorySettings {
+        Self::get_memory_options(&self.0.borrow())
+    }
+
     fn get_memory_options(binding: &Ref<Rewriter>) -> MemorySettings {
         let options = &binding.options;
         MemorySettings::new()
@@ -571,6 +838,8 @@ impl RewriterOptions {
         Self {
             max_allowed_memory_usage: Self::DEFAULT_MAX_ALLOWED_MEMORY_USAGE,
             preallocated_parsing_buffer_size: Self::DEFAULT_PREALLOCATED_PARSING_BUFFER_SIZE,
+            limits: Limits::default(),
+            timeout: None,
         }
     }
 }
@@ -581,10 +850,16 @@ pub fn init(m_selma: RModule) -> Result<(), magnus::Error> {
         .define_class("Rewriter", ruby.class_object())
         .expect("cannot define class Selma::Rewriter");
 
+    c_rewriter.define_error("LimitExceededError", ruby.exception_runtime_error())?;
+    c_rewriter.define_error("TimeoutError", ruby.exception_runtime_error())?;
+
     c_rewriter.define_singleton_method("new", function!(SelmaRewriter::new, -1))?;
     c_rewriter
         .define_method("rewrite", method!(SelmaRewriter::rewrite, 1))
         .expect("cannot define method `rewrite`");
+    c_rewriter
+        .define_method("stats", method!(SelmaRewriter::stats, 0))
+        .expect("cannot define method `stats`");
 
     Ok(())
 }
@@ -1,41 +1,194 @@
-use std::{borrow::BorrowMut, collections::HashMap};
+use std::{
+    borrow::{BorrowMut, Cow},
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
+};
 
+use crate::{
+    clobber::{IdPrefix, DEFAULT_ID_PREFIX},
+    headings::{HeadingTexts, Headings},
+    limits::LimitTracker,
+    link_policy::LinkPolicy,
+    rewriter::SelmaRewriter,
+    sanitizer_config::config_name,
+    stats::Timing,
+    tags::{Namespace, Tag, TagInterner},
+    url::UrlList,
+    url_rewriter::UrlRewriting,
+};
 use lol_html::{
+    doc_comments, doctype, element,
     errors::AttributeNameError,
     html_content::{Comment, ContentType, Doctype, Element, EndTag},
+    DocumentContentHandlers, ElementContentHandlers, HandlerResult, MemorySettings, Selector,
 };
 use magnus::{
-    eval, function, method,
+    eval, function, gc, kwargs, method,
     r_hash::ForEach,
     scan_args,
+    typed_data::Obj,
     value::{Opaque, ReprValue},
-    Module, Object, RArray, RHash, RModule, RString, Ruby, Symbol, Value,
+    DataTypeFunctions, Module, Object, RArray, RHash, RModule, Ruby, Symbol, TypedData, Value,
 };
 
+/// Every attribute name mentioned in a config, each given a small id, so that the attributes
+/// allowed on an element can be kept as a bitset.
+#[derive(Debug, Default)]
+struct AttributeNames {
+    ids: HashMap<String, usize>,
+    names: Vec<String>,
+}
+
+impl AttributeNames {
+    fn intern(&mut self, attr_name: &str) -> usize {
+        if let Some(id) = self.ids.get(attr_name) {
+            return *id;
+        }
+
+        let id = self.names.len();
+        self.names.push(attr_name.to_string());
+        self.ids.insert(attr_name.to_string(), id);
+        id
+    }
+
+    fn id_of(&self, attr_name: &str) -> Option<usize> {
+        self.ids.get(attr_name).copied()
+    }
+
+    fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+}
+
+/// A set of attribute names, by their `AttributeNames` id.
+#[derive(Clone, Debug, Default)]
+struct AttributeSet {
+    bits: Vec<u64>,
+    // every `data-*` attribute, from `:data`
+    data_attributes: bool,
+}
+
+impl AttributeSet {
+    fn insert(&mut self, id: usize) {
+        let (word, bit) = (id / 64, id % 64);
+        if self.bits.len() <= word {
+            self.bits.resize(word + 1, 0);
+        }
+        self.bits[word] |= 1 << bit;
+    }
+
+    /// Whether `attr_name`, whose id is `id` (if it has one), is in the set.
+    fn contains(&self, id: Option<usize>, attr_name: &str) -> bool {
+        let listed = id.is_some_and(|id| {
+            self.bits
+                .get(id / 64)
+                .is_some_and(|word| word & (1 << (id % 64)) != 0)
+        });
+
+        listed
+            || (self.data_attributes
+                && attr_name.len() > "data-".len()
+                && attr_name.starts_with("data-"))
+    }
+
+    fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.bits.len() * 64).filter(|id| self.bits[id / 64] & (1 << (id % 64)) != 0)
+    }
+}
+
+thread_local! {
+    // reused from one element to the next, so that sanitizing an element's attributes doesn't
+    // allocate a new list every time. Each name and value in it is still its own `String`:
+    // `lol_html` only hands attributes out as owned copies, and the element gets changed while
+    // its attributes are walked, so they can't be borrowed from it.
+    static ATTRIBUTES: Cell<Vec<(String, String)>> = const { Cell::new(Vec::new()) };
+}
+
 #[derive(Clone, Debug, Default)]
 struct ElementSanitizer {
-    allowed_attrs: Vec<String>,
-    required_attrs: Vec<String>,
-    allowed_classes: Vec<String>,
+    allowed_attrs: AttributeSet,
+    allowed_classes: HashSet<String>,
+    // protocol lists are short enough that a scan beats hashing
     protocol_sanitizers: HashMap<String, Vec<String>>,
 }
 
-#[derive(Clone)]
-pub struct Sanitizer {
-    flags: [u8; crate::tags::Tag::TAG_COUNT],
-    allowed_attrs: Vec<String>,
-    allowed_classes: Vec<String>,
+/// The rules for elements parsed into a foreign (SVG or MathML) namespace. Such elements are
+/// judged against these alone, never against the HTML `elements` and `attributes`.
+#[derive(Clone, Debug, Default)]
+struct ForeignContentRules {
+    allowed_elements: HashSet<String>,
+    allowed_attrs: AttributeSet,
     element_sanitizers: HashMap<String, ElementSanitizer>,
+}
+
+impl ForeignContentRules {
+    fn allows(&self, namespace: Namespace, mut tag_name: String) -> bool {
+        tag_name.make_ascii_lowercase();
+
+        !namespace.is_forbidden_integration_point(&tag_name)
+            && self.allowed_elements.contains(&tag_name)
+    }
+}
+
+/// What to do with custom elements (e.g. `<my-widget>`) not listed in `elements`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum CustomElements {
+    #[default]
+    Remove,
+    Allow,
+}
+
+/// A sanitizer config, compiled into lookups. Nothing in it changes once it's built, so one set
+/// of rules can be shared by every rewriter, thread and Ractor using the same sanitizer.
+pub struct CompiledRules {
+    // indexed by `Tag::index`, followed by a slot for each name in `tag_interner`
+    flags: Vec<u8>,
+    tag_interner: TagInterner,
+    custom_elements: CustomElements,
+    // lowercase element name => the name it's given, before it's judged
+    renamed_elements: HashMap<String, String>,
+    attribute_names: AttributeNames,
+    allowed_attrs: AttributeSet,
+    allowed_classes: HashSet<String>,
+    element_sanitizers: HashMap<String, ElementSanitizer>,
+    svg_rules: Option<ForeignContentRules>,
+    mathml_rules: Option<ForeignContentRules>,
+    url_rewriting: UrlRewriting,
+    link_policy: Option<LinkPolicy>,
+    id_prefix: Option<IdPrefix>,
+    headings: Headings,
 
     pub escape_tagfilter: bool,
     pub allow_comments: bool,
     pub allow_doctype: bool,
+}
+
+impl CompiledRules {
+    fn foreign_content(&self, namespace: Namespace) -> Option<&ForeignContentRules> {
+        match namespace {
+            Namespace::Html => None,
+            Namespace::Svg => self.svg_rules.as_ref(),
+            Namespace::MathML => self.mathml_rules.as_ref(),
+        }
+    }
+}
+
+#[derive(Clone, TypedData)]
+#[magnus(class = "Selma::Sanitizer", free_immediately, mark, frozen_shareable)]
+pub struct SelmaSanitizer {
+    rules: Arc<CompiledRules>,
+    // deeply frozen, so that the sanitizer itself can be shared between Ractors
     config: Opaque<RHash>,
 }
 
-#[derive(Clone)]
-#[magnus::wrap(class = "Selma::Sanitizer")]
-pub struct SelmaSanitizer(std::cell::RefCell<Sanitizer>);
+impl DataTypeFunctions for SelmaSanitizer {
+    fn mark(&self, marker: &gc::Marker) {
+        marker.mark(self.config);
+        self.rules.url_rewriting.mark(marker);
+    }
+}
 
 impl SelmaSanitizer {
     const SELMA_SANITIZER_ALLOW: u8 = (1 << 0);
@@ -43,6 +196,9 @@ impl SelmaSanitizer {
     const SELMA_SANITIZER_REMOVE_CONTENTS: u8 = (1 << 2);
     const SELMA_SANITIZER_WRAP_WHITESPACE: u8 = (1 << 3);
 
+    // how `allowed_attributes` shows `:data`, which allows every `data-*` attribute
+    const DATA_ATTRIBUTES: &'static str = "data-*";
+
     pub fn new(arguments: &[Value]) -> Result<Self, magnus::Error> {
         let args = scan_args::scan_args::<(), (Option<RHash>,), (), (), (), ()>(arguments)?;
         let (opt_config,): (Option<RHash>,) = args.optional;
@@ -55,15 +211,29 @@ impl SelmaSanitizer {
             None => magnus::eval::<RHash>(r#"Selma::Sanitizer::Config::DEFAULT"#).unwrap(),
         };
 
-        let mut flags = [0; crate::tags::Tag::TAG_COUNT];
-        let mut sanitizer_allowed_attrs = vec![];
-        let sanitizer_allowed_classes = vec![];
-        match Self::setup_config(&mut flags, config) {
+        let strict = match config.get(ruby.to_symbol("strict")) {
+            Some(value) => value.to_bool(),
+            None => true,
+        };
+        if strict {
+            crate::sanitizer_config::validate(config)?;
+        }
+
+        // every tag name gets its flag slot up front, so that `remove_contents: true` also
+        // covers the names configured after it
+        let tag_interner = Self::intern_tag_names(config)?;
+        let mut flags = vec![0; tag_interner.slot_count()];
+        let mut attribute_names = AttributeNames::default();
+        let mut sanitizer_allowed_attrs = AttributeSet::default();
+        let sanitizer_allowed_classes = HashSet::new();
+        match Self::setup_config(&mut flags, &tag_interner, config) {
             Ok(_) => {}
             Err(e) => {
                 return Err(e);
             }
         };
+        let custom_elements = Self::setup_custom_elements(&mut flags, &tag_interner, config)?;
+        let renamed_elements = Self::setup_rename_elements(config)?;
 
         let mut element_sanitizers = HashMap::new();
 
@@ -75,35 +245,171 @@ impl SelmaSanitizer {
             element_sanitizers.insert(element_name, element_sanitizer);
         });
 
-        // def allow_attribute(element, attrs)
-        //   attrs.flatten.each { |attr| set_allowed_attribute(element, attr, true) }
-        // end
+        Self::setup_attributes(
+            config,
+            &mut attribute_names,
+            &mut sanitizer_allowed_attrs,
+            &mut element_sanitizers,
+        )?;
+        Self::setup_protocols(config, &mut element_sanitizers)?;
+
+        let svg_rules = Self::setup_foreign_content(config, "svg", &mut attribute_names)?;
+        let mathml_rules = Self::setup_foreign_content(config, "math", &mut attribute_names)?;
+
+        let url_rewriting = UrlRewriting::from_config(config)?;
+        let link_policy = LinkPolicy::from_config(config)?;
+        let id_prefix = IdPrefix::from_config(config)?;
+        // heading ids are linked to like any other, so they share the prefix, or get the same
+        // default one
+        let headings = Headings::from_config(
+            config,
+            id_prefix
+                .as_ref()
+                .map_or(DEFAULT_ID_PREFIX, IdPrefix::as_str),
+        )?;
+
+        let escape_tagfilter = match config.get(ruby.to_symbol("escape_tagfilter")) {
+            Some(value) => value.to_bool(),
+            None => true,
+        };
+
+        let allow_comments = match config.get(ruby.to_symbol("allow_comments")) {
+            Some(value) => value.to_bool(),
+            None => false,
+        };
+
+        let allow_doctype = match config.get(ruby.to_symbol("allow_doctype")) {
+            Some(value) => value.to_bool(),
+            None => true,
+        };
+
+        Ok(Self {
+            rules: Arc::new(CompiledRules {
+                flags,
+                tag_interner,
+                custom_elements,
+                renamed_elements,
+                attribute_names,
+                allowed_attrs: sanitizer_allowed_attrs,
+                allowed_classes: sanitizer_allowed_classes,
+                element_sanitizers,
+                svg_rules,
+                mathml_rules,
+                url_rewriting,
+                link_policy,
+                id_prefix,
+                headings,
+
+                escape_tagfilter,
+                allow_comments,
+                allow_doctype,
+            }),
+            config: Self::shareable_config(config)?.into(),
+        })
+    }
+
+    /// `Selma::Sanitizer.new`, which hands out frozen sanitizers.
+    fn rb_new(arguments: &[Value]) -> Result<Obj<Self>, magnus::Error> {
+        let sanitizer = Ruby::get().unwrap().obj_wrap(Self::new(arguments)?);
+        sanitizer.freeze();
+
+        Ok(sanitizer)
+    }
+
+    /// `config`, if it's already deeply frozen, or else a deeply frozen copy of it.
+    fn shareable_config(config: RHash) -> Result<RHash, magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+        let ractor: Value = ruby.class_object().const_get("Ractor")?;
+
+        if ractor.funcall::<_, _, bool>("shareable?", (config,))? {
+            return Ok(config);
+        }
+        let copy = Self::frozen_copy(ractor, config.as_value())?;
+        Ok(RHash::from_value(copy).unwrap())
+    }
+
+    /// A deeply frozen copy of `value`, save for any callables in it (`url_rewriter:`), which
+    /// are kept as they are: they belong to the caller, and making them shareable would change
+    /// them behind the caller's back. A sanitizer holding an unshareable callable is then not
+    /// shareable itself, since Ractor checks everything a sanitizer marks.
+    fn frozen_copy(ractor: Value, value: Value) -> Result<Value, magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+
+        if let Some(hash) = RHash::from_value(value) {
+            let copy = ruby.hash_new();
+            hash.foreach(|key: Value, value: Value| {
+                copy.aset(
+                    Self::frozen_copy(ractor, key)?,
+                    Self::frozen_copy(ractor, value)?,
+                )?;
+                Ok(ForEach::Continue)
+            })?;
+            copy.freeze();
+            return Ok(copy.as_value());
+        }
+        if let Some(array) = RArray::from_value(value) {
+            let copy = ruby.ary_new_capa(array.len());
+            for item in array.to_vec::<Value>()? {
+                copy.push(Self::frozen_copy(ractor, item)?)?;
+            }
+            copy.freeze();
+            return Ok(copy.as_value());
+        }
+        if value.respond_to("call", false)? {
+            return Ok(value);
+        }
+
+        ractor.funcall("make_shareable", (value, kwargs!("copy" => true)))
+    }
+
+    fn intern_tag_names(config: RHash) -> Result<TagInterner, magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+        let mut tag_interner = TagInterner::default();
+
+        for key in [
+            "elements",
+            "remove_contents",
+            "whitespace_elements",
+            "custom_elements",
+        ] {
+            if let Some(elements) = config.get(ruby.to_symbol(key)).and_then(RArray::from_value) {
+                for element in elements.into_iter() {
+                    if let Some(element_name) = config_name(element) {
+                        tag_interner.intern(&element_name);
+                    }
+                }
+            }
+        }
+
+        Ok(tag_interner)
+    }
+
+    // def allow_attribute(element, attrs)
+    //   attrs.flatten.each { |attr| set_allowed_attribute(element, attr, true) }
+    // end
+    fn setup_attributes(
+        config: RHash,
+        attribute_names: &mut AttributeNames,
+        allowed_attrs: &mut AttributeSet,
+        element_sanitizers: &mut HashMap<String, ElementSanitizer>,
+    ) -> Result<(), magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+
         if let Some(value) = config.get(ruby.to_symbol("attributes")) {
             if let Some(allowed_attributes) = RHash::from_value(value) {
                 allowed_attributes.foreach(|element_value: Value, attributes: RArray| {
+                    let element = config_name(element_value).unwrap_or_default();
+                    let attribute_set = if element == "all" {
+                        &mut *allowed_attrs
+                    } else {
+                        &mut Self::get_element_sanitizer(element_sanitizers, &element).allowed_attrs
+                    };
+
                     attributes.into_iter().for_each(|attr: Value| {
-                        match RString::from_value(attr) {
-                            None => {}
-                            Some(attribute_name) => {
-                                let attr_name = attribute_name.to_string().unwrap();
-                                let element = match element_value.to_r_string() {
-                                    Err(_) => "".to_string(),
-                                    Ok(element_name) => element_name.to_string().unwrap(),
-                                };
-                                if element == "all" {
-                                    Self::set_allowed(
-                                        &mut sanitizer_allowed_attrs,
-                                        &attr_name,
-                                        true,
-                                    );
-                                } else {
-                                    let element_sanitizer = Self::get_element_sanitizer(
-                                        &mut element_sanitizers,
-                                        &element,
-                                    );
-                                    element_sanitizer.allowed_attrs.push(attr_name);
-                                }
-                            }
+                        if Self::is_data_symbol(attr) {
+                            attribute_set.data_attributes = true;
+                        } else if let Some(attr_name) = config_name(attr) {
+                            attribute_set.insert(attribute_names.intern(&attr_name));
                         }
                     });
 
@@ -112,18 +418,29 @@ impl SelmaSanitizer {
             }
         };
 
-        // def allow_protocol(element, attr, protos)
-        //  if protos.is_a?(Array)
-        //    raise ArgumentError, "`:all` must be passed outside of an array" if protos.include?(:all)
-        //  else
-        //    protos = [protos]
-        //  end
-        //  set_allowed_protocols(element, attr, protos)
-        // end
+        Ok(())
+    }
+
+    // def allow_protocol(element, attr, protos)
+    //  if protos.is_a?(Array)
+    //    raise ArgumentError, "`:all` must be passed outside of an array" if protos.include?(:all)
+    //  else
+    //    protos = [protos]
+    //  end
+    //  set_allowed_protocols(element, attr, protos)
+    // end
+    fn setup_protocols(
+        config: RHash,
+        element_sanitizers: &mut HashMap<String, ElementSanitizer>,
+    ) -> Result<(), magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+
         if let Some(value) = config.get(ruby.to_symbol("protocols")) {
             if let Some(allowed_protocols) = RHash::from_value(value) {
-                allowed_protocols.foreach(|element_name: String, protocols: RHash| {
-                    protocols.foreach(|attribute_name: String, protocol_list: Value| {
+                allowed_protocols.foreach(|element_name: Value, protocols: RHash| {
+                    let element_name = config_name(element_name).unwrap_or_default();
+                    protocols.foreach(|attribute_name: Value, protocol_list: Value| {
+                        let attribute_name = config_name(attribute_name).unwrap_or_default();
                         let protocols: RArray;
                         if protocol_list.is_kind_of(ruby.class_array()) {
                             protocols = RArray::from_value(protocol_list).unwrap();
@@ -146,7 +463,7 @@ impl SelmaSanitizer {
                         }
 
                         let element_sanitizer =
-                            Self::get_element_sanitizer(&mut element_sanitizers, &element_name);
+                            Self::get_element_sanitizer(element_sanitizers, &element_name);
 
                         Self::set_allowed_protocols(element_sanitizer, attribute_name, protocols);
                         Ok(ForEach::Continue)
@@ -157,36 +474,177 @@ impl SelmaSanitizer {
             }
         }
 
-        let escape_tagfilter = match config.get(ruby.to_symbol("escape_tagfilter")) {
-            Some(value) => value.to_bool(),
-            None => true,
+        Ok(())
+    }
+
+    /// The rules under `foreign_content[namespace]`, if that namespace is configured at all.
+    fn setup_foreign_content(
+        config: RHash,
+        namespace: &str,
+        attribute_names: &mut AttributeNames,
+    ) -> Result<Option<ForeignContentRules>, magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+
+        let Some(value) = config.get(ruby.to_symbol("foreign_content")) else {
+            return Ok(None);
+        };
+        let Some(foreign_content) = RHash::from_value(value) else {
+            return Err(magnus::Error::new(
+                ruby.exception_arg_error(),
+                "foreign_content must be a Hash".to_string(),
+            ));
+        };
+        let Some(value) = foreign_content
+            .get(ruby.to_symbol(namespace))
+            .or_else(|| foreign_content.get(namespace))
+        else {
+            return Ok(None);
+        };
+        let Some(rules) = RHash::from_value(value) else {
+            return Err(magnus::Error::new(
+                ruby.exception_arg_error(),
+                format!("foreign_content[:{namespace}] must be a Hash"),
+            ));
         };
 
-        let allow_comments = match config.get(ruby.to_symbol("allow_comments")) {
-            Some(value) => value.to_bool(),
-            None => false,
+        let mut foreign_content_rules = ForeignContentRules::default();
+        if let Some(elements) = rules
+            .get(ruby.to_symbol("elements"))
+            .and_then(RArray::from_value)
+        {
+            for element in elements.into_iter() {
+                if let Some(element_name) = config_name(element) {
+                    foreign_content_rules.allowed_elements.insert(element_name);
+                }
+            }
+        }
+        Self::setup_attributes(
+            rules,
+            attribute_names,
+            &mut foreign_content_rules.allowed_attrs,
+            &mut foreign_content_rules.element_sanitizers,
+        )?;
+        Self::setup_protocols(rules, &mut foreign_content_rules.element_sanitizers)?;
+
+        Ok(Some(foreign_content_rules))
+    }
+
+    fn setup_custom_elements(
+        flags: &mut [u8],
+        tag_interner: &TagInterner,
+        config: RHash,
+    ) -> Result<CustomElements, magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+        let invalid = || {
+            magnus::Error::new(
+                ruby.exception_arg_error(),
+                "custom_elements must be `:allow`, `:remove`, or an array of custom element names"
+                    .to_string(),
+            )
         };
 
-        let allow_doctype = match config.get(ruby.to_symbol("allow_doctype")) {
-            Some(value) => value.to_bool(),
-            None => true,
+        let Some(value) = config.get(ruby.to_symbol("custom_elements")) else {
+            return Ok(CustomElements::default());
+        };
+
+        if let Some(policy) = Symbol::from_value(value) {
+            return match policy.name()?.as_ref() {
+                "allow" => Ok(CustomElements::Allow),
+                "remove" => Ok(CustomElements::Remove),
+                _ => Err(invalid()),
+            };
+        }
+
+        let Some(elements) = RArray::from_value(value) else {
+            return Err(invalid());
+        };
+        for element in elements.into_iter() {
+            let Some(element_name) = config_name(element) else {
+                return Err(invalid());
+            };
+            if !Tag::is_valid_custom_element_name(&element_name) {
+                return Err(magnus::Error::new(
+                    ruby.exception_arg_error(),
+                    format!("`{element_name}` is not a valid custom element name"),
+                ));
+            }
+            Self::set_flag(
+                element_name,
+                flags,
+                tag_interner,
+                Self::SELMA_SANITIZER_ALLOW,
+                true,
+            );
+        }
+
+        Ok(CustomElements::Remove)
+    }
+
+    /// `rename_elements`, as a map from lowercase names to what they become. Elements whose
+    /// contents aren't parsed as HTML can't be renamed, nor can others take their place, and
+    /// void elements (like `<br>`) can only be swapped for each other, since the contents were
+    /// already parsed according to the original name.
+    fn setup_rename_elements(config: RHash) -> Result<HashMap<String, String>, magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+        let mut renamed_elements = HashMap::new();
+
+        let Some(value) = config.get(ruby.to_symbol("rename_elements")) else {
+            return Ok(renamed_elements);
+        };
+        if value.is_nil() {
+            return Ok(renamed_elements);
+        }
+        let Some(renames) = RHash::from_value(value) else {
+            return Err(magnus::Error::new(
+                ruby.exception_arg_error(),
+                format!(
+                    "`rename_elements` must be a Hash of element names, not `{}`",
+                    value.inspect()
+                ),
+            ));
         };
 
-        Ok(Self(std::cell::RefCell::new(Sanitizer {
-            flags,
-            allowed_attrs: sanitizer_allowed_attrs,
-            allowed_classes: sanitizer_allowed_classes,
-            element_sanitizers,
+        renames.foreach(|from: Value, to: Value| {
+            let (Some(from), Some(to)) = (config_name(from), config_name(to)) else {
+                return Err(magnus::Error::new(
+                    ruby.exception_arg_error(),
+                    format!(
+                        "`rename_elements` must map element names to element names; found `{} => {}`",
+                        from.inspect(),
+                        to.inspect()
+                    ),
+                ));
+            };
+
+            let from_tag = Tag::tag_from_tag_name(&from);
+            let to_tag = Tag::tag_from_tag_name(&to);
+            let error = if Tag::is_unknown(to_tag) && !Tag::is_valid_custom_element_name(&to) {
+                Some(format!("`{to}` is not an HTML element or a valid custom element name"))
+            } else if Tag::is_tag_escapeworthy(from_tag) || Tag::is_tag_escapeworthy(to_tag) {
+                Some(format!(
+                    "`{from}` can't be renamed to `{to}`, as the contents of one of them aren't parsed as HTML"
+                ))
+            } else if from_tag.self_closing != to_tag.self_closing {
+                Some(format!(
+                    "`{from}` can't be renamed to `{to}`, as only one of them is a void element"
+                ))
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                return Err(magnus::Error::new(ruby.exception_arg_error(), error));
+            }
+
+            renamed_elements.insert(from, to);
+            Ok(ForEach::Continue)
+        })?;
 
-            escape_tagfilter,
-            allow_comments,
-            allow_doctype,
-            config: config.into(),
-        })))
+        Ok(renamed_elements)
     }
 
     fn setup_config(
-        flags: &mut [u8; crate::tags::Tag::TAG_COUNT],
+        flags: &mut [u8],
+        tag_interner: &TagInterner,
         config: RHash,
     ) -> Result<(), magnus::Error> {
         let ruby = Ruby::get().unwrap();
@@ -198,12 +656,13 @@ impl SelmaSanitizer {
             if let Some(elements) = RArray::from_value(value) {
                 elements
                     .into_iter()
-                    .for_each(|element| match RString::from_value(element) {
+                    .for_each(|element| match config_name(element) {
                         None => {}
                         Some(element_name) => {
                             Self::set_flag(
-                                element_name.to_string().unwrap(),
+                                element_name,
                                 flags,
+                                tag_interner,
                                 Self::SELMA_SANITIZER_ALLOW,
                                 true,
                             );
@@ -232,12 +691,13 @@ impl SelmaSanitizer {
                 let elements = RArray::from_value(remove_contents).unwrap();
                 elements
                     .into_iter()
-                    .for_each(|element| match RString::from_value(element) {
+                    .for_each(|element| match config_name(element) {
                         None => {}
                         Some(element_name) => {
                             Self::set_flag(
-                                element_name.to_string().unwrap(),
+                                element_name,
                                 flags,
+                                tag_interner,
                                 Self::SELMA_SANITIZER_REMOVE_CONTENTS,
                                 true,
                             );
@@ -258,12 +718,13 @@ impl SelmaSanitizer {
             if let Some(elements) = RArray::from_value(value) {
                 elements
                     .into_iter()
-                    .for_each(|element| match RString::from_value(element) {
+                    .for_each(|element| match config_name(element) {
                         None => {}
                         Some(element_name) => {
                             Self::set_flag(
-                                element_name.to_string().unwrap(),
+                                element_name,
                                 flags,
+                                tag_interner,
                                 Self::SELMA_SANITIZER_WRAP_WHITESPACE,
                                 true,
                             );
@@ -276,48 +737,371 @@ impl SelmaSanitizer {
     }
 
     fn get_config(&self) -> Result<RHash, magnus::Error> {
-        let binding = self.0.borrow();
         let ruby = Ruby::get().unwrap();
 
-        Ok(ruby.get_inner(binding.config))
+        Ok(ruby.get_inner(self.config))
+    }
+
+    /// The names of the HTML elements kept as is, sorted.
+    fn allowed_elements(&self) -> Vec<String> {
+        self.tag_names_with_flag(Self::SELMA_SANITIZER_ALLOW)
+    }
+
+    /// The names of the HTML elements removed along with their contents, sorted.
+    fn remove_contents_elements(&self) -> Vec<String> {
+        self.tag_names_with_flag(Self::SELMA_SANITIZER_REMOVE_CONTENTS)
+    }
+
+    fn tag_names_with_flag(&self, flag: u8) -> Vec<String> {
+        let binding = &self.rules;
+        let has_flag = |index: usize| binding.flags[index] & flag != 0;
+
+        let known_names = Tag::html_tags().iter().filter_map(|html_tag| {
+            let tag = Tag::tag_from_tag_name(Tag::element_name_from_enum(html_tag));
+            (!Tag::is_unknown(tag) && has_flag(tag.index)).then(|| tag.name.to_string())
+        });
+        let interned_names = binding
+            .tag_interner
+            .names()
+            .filter_map(|(tag_name, index)| has_flag(index).then(|| tag_name.to_string()));
+
+        let mut tag_names: Vec<String> = known_names.chain(interned_names).collect();
+        tag_names.sort();
+        tag_names
+    }
+
+    /// Whether an HTML element named `tag_name` is kept, rather than removed.
+    fn is_allowed(&self, tag_name: Value) -> Result<bool, magnus::Error> {
+        let Some(tag_name) = config_name(tag_name) else {
+            return Err(magnus::Error::new(
+                Ruby::get().unwrap().exception_arg_error(),
+                format!(
+                    "element name must be a String or Symbol, not `{}`",
+                    tag_name.inspect()
+                ),
+            ));
+        };
+
+        Ok(self.flags_for_name(&tag_name) & Self::SELMA_SANITIZER_ALLOW != 0)
+    }
+
+    /// The attributes kept on every HTML element, or with `for:`, on that element.
+    fn allowed_attributes(&self, args: &[Value]) -> Result<Vec<String>, magnus::Error> {
+        let args = scan_args::scan_args::<(), (), (), (), RHash, ()>(args)?;
+        let kwargs =
+            scan_args::get_kwargs::<_, (), (Option<Value>,), ()>(args.keywords, &[], &["for"])?;
+        let (element,) = kwargs.optional;
+
+        let binding = &self.rules;
+        let mut attribute_sets = vec![&binding.allowed_attrs];
+        if let Some(element) = element {
+            if let Some(element_sanitizer) = config_name(element)
+                .and_then(|element_name| binding.element_sanitizers.get(&element_name))
+            {
+                attribute_sets.push(&element_sanitizer.allowed_attrs);
+            }
+        }
+
+        let mut allowed_attrs: Vec<String> = vec![];
+        for attribute_set in attribute_sets {
+            allowed_attrs.extend(
+                attribute_set
+                    .ids()
+                    .map(|id| binding.attribute_names.name(id).to_string()),
+            );
+            if attribute_set.data_attributes {
+                allowed_attrs.push(Self::DATA_ATTRIBUTES.to_string());
+            }
+        }
+
+        allowed_attrs.sort();
+        allowed_attrs.dedup();
+        Ok(allowed_attrs)
+    }
+
+    /// The protocols `attribute` may use on `element`: `nil` if there is no protocol list,
+    /// `:all`, or an Array of protocols, where `:relative` stands for relative URLs.
+    fn allowed_protocols(&self, element: Value, attribute: Value) -> Result<Value, magnus::Error> {
+        let ruby = Ruby::get().unwrap();
+        let binding = &self.rules;
+
+        let protocols = config_name(element)
+            .and_then(|element_name| binding.element_sanitizers.get(&element_name))
+            .zip(config_name(attribute))
+            .and_then(|(element_sanitizer, attr_name)| {
+                element_sanitizer.protocol_sanitizers.get(&attr_name)
+            });
+        let Some(protocols) = protocols else {
+            return Ok(ruby.qnil().as_value());
+        };
+
+        if protocols.iter().any(|protocol| protocol == "all") {
+            return Ok(ruby.to_symbol("all").as_value());
+        }
+
+        let list = ruby.ary_new();
+        for protocol in protocols {
+            if protocol != "#" && protocol != "/" && !list.includes(protocol.as_str()) {
+                list.push(protocol.as_str())?;
+            }
+        }
+        if protocols
+            .iter()
+            .any(|protocol| protocol == "#" || protocol == "/")
+        {
+            list.push(ruby.to_symbol("relative"))?;
+        }
+
+        Ok(list.as_value())
     }
 
     /// Toggle a sanitizer option on or off.
     fn set_flag(
         tag_name: String,
-        flags: &mut [u8; crate::tags::Tag::TAG_COUNT],
+        flags: &mut [u8],
+        tag_interner: &TagInterner,
         flag: u8,
         set: bool,
     ) {
-        let tag = crate::tags::Tag::tag_from_tag_name(tag_name.as_str());
+        let tag_name = tag_name.to_ascii_lowercase();
+        let tag = Tag::tag_from_tag_name(tag_name.as_str());
+        let index = if Tag::is_unknown(tag) {
+            tag_interner.index_of(&tag_name).unwrap_or(tag.index)
+        } else {
+            tag.index
+        };
+
         if set {
-            flags[tag.index] |= flag;
+            flags[index] |= flag;
         } else {
-            flags[tag.index] &= !flag;
+            flags[index] &= !flag;
         }
     }
 
     /// Toggles all sanitization options on or off.
-    fn set_all_flags(flags: &mut [u8; crate::tags::Tag::TAG_COUNT], flag: u8, set: bool) {
-        if set {
-            crate::tags::Tag::html_tags()
-                .iter()
-                .enumerate()
-                .for_each(|(iter, _)| {
-                    flags[iter] |= flag;
-                });
-        } else {
-            crate::tags::Tag::html_tags()
-                .iter()
-                .enumerate()
-                .for_each(|(iter, _)| {
-                    flags[iter] &= flag;
-                });
+    fn set_all_flags(flags: &mut [u8], flag: u8, set: bool) {
+        flags.iter_mut().for_each(|flags| {
+            if set {
+                *flags |= flag;
+            } else {
+                *flags &= !flag;
+            }
+        });
+    }
+
+    /// The flags for `element`, whose `Tag` is `tag`.
+    fn flags_for(&self, tag: Tag, element: &Element) -> u8 {
+        if !Tag::is_unknown(tag) {
+            return self.rules.flags[tag.index];
+        }
+
+        self.flags_for_name(&element.tag_name().to_ascii_lowercase())
+    }
+
+    /// The flags for the (lowercase) `tag_name`.
+    fn flags_for_name(&self, tag_name: &str) -> u8 {
+        let binding = &self.rules;
+        let tag = Tag::tag_from_tag_name(tag_name);
+        if !Tag::is_unknown(tag) {
+            return binding.flags[tag.index];
+        }
+
+        match binding.tag_interner.index_of(tag_name) {
+            Some(index) => binding.flags[index],
+            None => {
+                let flags = binding.flags[tag.index];
+                if binding.custom_elements == CustomElements::Allow
+                    && Tag::is_valid_custom_element_name(&tag_name)
+                {
+                    flags | Self::SELMA_SANITIZER_ALLOW
+                } else {
+                    flags
+                }
+            }
         }
     }
 
+    /// The handlers for the first sanitization pass: doctypes and comments (unless they're
+    /// allowed), then every element. Each call is timed by `timing`, and `elements_removed`
+    /// counts the elements taken out.
+    pub fn sanitization_handlers<'a>(
+        &'a self,
+        timing: &'a Timing,
+        elements_removed: &'a Cell<usize>,
+    ) -> (
+        Vec<DocumentContentHandlers<'a>>,
+        Vec<(Cow<'a, Selector>, ElementContentHandlers<'a>)>,
+    ) {
+        let mut document_content_handlers: Vec<DocumentContentHandlers> = vec![];
+        let mut element_content_handlers: Vec<(Cow<Selector>, ElementContentHandlers)> = vec![];
+
+        if !self.get_allow_doctype() {
+            document_content_handlers.push(doctype!(move |d| {
+                timing.time(|| self.remove_doctype(d));
+                Ok(())
+            }));
+        }
+        if !self.get_allow_comments() {
+            document_content_handlers.push(doc_comments!(move |c| {
+                timing.time(|| self.remove_comment(c));
+                Ok(())
+            }));
+        }
+        element_content_handlers.push(element!("*", move |el| {
+            timing.time(|| {
+                if self.try_remove_element(el) {
+                    elements_removed.set(elements_removed.get() + 1);
+                }
+                if el.removed() {
+                    return Ok(());
+                }
+                // if it was removed, there are no attributes to sanitize
+                self.sanitize_attributes(el)
+            })
+        }));
+
+        (document_content_handlers, element_content_handlers)
+    }
+
+    /// The handlers for the final sanitization pass, which catches the dangerous tags that
+    /// only come together once the first pass has run, counting them in `elements_removed`. If
+    /// headings are to be given ids, it also collects their text into `heading_texts`.
+    pub fn final_sanitization_handlers<'a>(
+        &'a self,
+        heading_texts: &Rc<RefCell<HeadingTexts>>,
+        elements_removed: &'a Cell<usize>,
+    ) -> Vec<(Cow<'a, Selector>, ElementContentHandlers<'a>)> {
+        let mut element_content_handlers: Vec<(Cow<Selector>, ElementContentHandlers)> = vec![];
+
+        if self.get_escape_tagfilter() {
+            element_content_handlers.push(element!(Tag::ESCAPEWORTHY_TAGS_CSS, move |el| {
+                let should_remove = self.allow_element(el);
+                if should_remove {
+                    self.force_remove_element(el);
+                    elements_removed.set(elements_removed.get() + 1);
+                }
+
+                Ok(())
+            }));
+        }
+        if self.rules.headings.gives_ids() {
+            element_content_handlers.extend(Headings::collection_handlers(heading_texts));
+        }
+
+        element_content_handlers
+    }
+
+    /// The handlers for one last pass, after the final sanitization pass, which gives headings
+    /// the ids made from the text it collected. Empty, so that the pass can be skipped, unless
+    /// `headings` asks for ids. With `recounted`, they collect the text of the headings again,
+    /// as this pass sees them.
+    pub fn heading_id_handlers(
+        &self,
+        heading_texts: &Rc<RefCell<HeadingTexts>>,
+        recounted: Option<&Rc<RefCell<HeadingTexts>>>,
+    ) -> Vec<(Cow<'_, Selector>, ElementContentHandlers<'_>)> {
+        let mut element_content_handlers = self.rules.headings.id_handlers(&heading_texts.borrow());
+        if let Some(recounted) = recounted {
+            if !element_content_handlers.is_empty() {
+                element_content_handlers.extend(Headings::collection_handlers(recounted));
+            }
+        }
+
+        element_content_handlers
+    }
+
+    /// @yard
+    /// @def sanitize(html)
+    /// Sanitizes `html` in the same two passes as `Selma::Rewriter#rewrite`, without any handlers.
+    /// @param html [String] The HTML to sanitize
+    /// @return [String]
+    fn sanitize(&self, html: String) -> Result<String, magnus::Error> {
+        let timing = Timing::default();
+        let elements_removed = Cell::new(0);
+
+        let (document_content_handlers, element_content_handlers) =
+            self.sanitization_handlers(&timing, &elements_removed);
+        let sanitized = SelmaRewriter::run_pass(
+            MemorySettings::new(),
+            document_content_handlers,
+            element_content_handlers,
+            None,
+            html.as_bytes(),
+        )?;
+        let sanitized =
+            self.run_final_passes(&MemorySettings::new, None, &elements_removed, &sanitized)?;
+
+        String::from_utf8(sanitized).map_err(|err| {
+            magnus::Error::new(
+                Ruby::get().unwrap().exception_runtime_error(),
+                format!("{err:?}"),
+            )
+        })
+    }
+
+    /// Runs the passes which follow the first one over `html`: the final sanitization pass, and
+    /// then the one giving headings their ids, if they get any. When there's a `limit_tracker`,
+    /// its deadline covers these passes too. Elements the final pass removes are added to
+    /// `elements_removed`.
+    pub fn run_final_passes(
+        &self,
+        memory_settings: &dyn Fn() -> MemorySettings,
+        limit_tracker: Option<&Rc<LimitTracker>>,
+        elements_removed: &Cell<usize>,
+        html: &[u8],
+    ) -> Result<Vec<u8>, magnus::Error> {
+        let heading_texts = Rc::default();
+        let interrupt_check_handlers = || {
+            limit_tracker
+                .map(|tracker| tracker.interrupt_check_handlers())
+                .unwrap_or_default()
+        };
+
+        let (document_content_handlers, mut element_content_handlers) = interrupt_check_handlers();
+        element_content_handlers
+            .extend(self.final_sanitization_handlers(&heading_texts, elements_removed));
+        let sanitized = SelmaRewriter::run_pass(
+            memory_settings(),
+            document_content_handlers,
+            element_content_handlers,
+            limit_tracker.map(Rc::as_ref),
+            html,
+        )?;
+
+        if !self.rules.headings.gives_ids() {
+            return Ok(sanitized);
+        }
+
+        let run_id_pass =
+            |heading_texts: &Rc<RefCell<HeadingTexts>>,
+             recounted: Option<&Rc<RefCell<HeadingTexts>>>| {
+                let (document_content_handlers, mut element_content_handlers) =
+                    interrupt_check_handlers();
+                element_content_handlers.extend(self.heading_id_handlers(heading_texts, recounted));
+                SelmaRewriter::run_pass(
+                    memory_settings(),
+                    document_content_handlers,
+                    element_content_handlers,
+                    limit_tracker.map(Rc::as_ref),
+                    &sanitized,
+                )
+            };
+
+        // ids are handed out by position, but the final sanitization pass can remove a heading
+        // after collecting its text, like one inside an svg `<title>`, which would shift every
+        // id after it onto the wrong heading; so this pass counts the headings again, and if
+        // they don't match, it's run once more with what it counted, which is exactly its html
+        let recounted: Rc<RefCell<HeadingTexts>> = Rc::default();
+        let with_ids = run_id_pass(&heading_texts, Some(&recounted))?;
+        if recounted.borrow().matches(&heading_texts.borrow()) {
+            return Ok(with_ids);
+        }
+
+        run_id_pass(&recounted, None)
+    }
+
     pub fn escape_tagfilter(&self, e: &mut Element) -> bool {
-        if self.0.borrow().escape_tagfilter {
+        if self.rules.escape_tagfilter {
             let tag = crate::tags::Tag::tag_from_element(e);
             if crate::tags::Tag::is_tag_escapeworthy(tag) {
                 e.remove();
@@ -329,11 +1113,11 @@ impl SelmaSanitizer {
     }
 
     pub fn get_escape_tagfilter(&self) -> bool {
-        self.0.borrow().escape_tagfilter
+        self.rules.escape_tagfilter
     }
 
     pub fn get_allow_comments(&self) -> bool {
-        self.0.borrow().allow_comments
+        self.rules.allow_comments
     }
 
     pub fn remove_comment(&self, c: &mut Comment) {
@@ -342,7 +1126,7 @@ impl SelmaSanitizer {
 
     /// Whether or not to keep HTML doctype.
     pub fn get_allow_doctype(&self) -> bool {
-        self.0.borrow().allow_doctype
+        self.rules.allow_doctype
     }
 
     pub fn remove_doctype(&self, d: &mut Doctype) {
@@ -389,33 +1173,45 @@ impl SelmaSanitizer {
         }
     }
 
-    fn set_allowed(set: &mut Vec<String>, attr_name: &String, allow: bool) {
-        if allow {
-            set.push(attr_name.to_string());
-        } else if set.contains(attr_name) {
-            set.swap_remove(set.iter().position(|x| x == attr_name).unwrap());
-        }
+    fn is_data_symbol(value: Value) -> bool {
+        Symbol::from_value(value).is_some_and(|s| s.name().is_ok_and(|name| name == "data"))
     }
 
-    pub fn sanitize_attributes(&self, element: &mut Element) -> Result<(), AttributeNameError> {
-        let tag = crate::tags::Tag::tag_from_element(element);
-        let tag_name = &element.tag_name();
-        let element_sanitizer = {
-            let mut binding = self.0.borrow_mut();
-            let element_sanitizers = &mut binding.element_sanitizers;
-            Self::get_element_sanitizer(element_sanitizers, tag_name).clone()
-        };
+    pub fn sanitize_attributes(&self, element: &mut Element) -> HandlerResult {
+        let mut attributes = ATTRIBUTES.take();
+        attributes.extend(element.attributes().iter().map(|a| (a.name(), a.value())));
 
-        let binding = self.0.borrow();
+        let result = self.sanitize_attribute_list(element, &attributes);
 
-        // FIXME: This is a hack to get around the fact that we can't borrow
-        let attribute_map: HashMap<String, String> = element
-            .attributes()
-            .iter()
-            .map(|a| (a.name(), a.value()))
-            .collect();
+        attributes.clear();
+        ATTRIBUTES.set(attributes);
+        result
+    }
 
-        for (attr_name, attr_val) in attribute_map.iter() {
+    fn sanitize_attribute_list(
+        &self,
+        element: &mut Element,
+        attributes: &[(String, String)],
+    ) -> HandlerResult {
+        let tag = crate::tags::Tag::tag_from_element(element);
+        let mut tag_name = element.tag_name();
+        tag_name.make_ascii_lowercase();
+
+        let binding = &self.rules;
+        let namespace = Namespace::of(element);
+        let foreign_content = binding.foreign_content(namespace);
+        let element_sanitizers = foreign_content.map_or(&binding.element_sanitizers, |rules| {
+            &rules.element_sanitizers
+        });
+        let element_sanitizer = element_sanitizers.get(&tag_name);
+        let link_policy = binding
+            .link_policy
+            .as_ref()
+            .filter(|_| foreign_content.is_none() && tag_name == "a");
+        let mut is_external_link = false;
+
+        let mut buf = String::new();
+        for (attr_name, attr_val) in attributes {
             // you can actually embed <!-- ... --> inside
             // an HTML tag to pass malicious data. If this is
             // encountered, remove the entire element to be safe.
@@ -426,63 +1222,117 @@ impl SelmaSanitizer {
 
             // first, trim leading spaces and unescape any encodings
             let trimmed = attr_val.trim_start();
-            let x = escapist::unescape_html(trimmed.as_bytes());
-            let unescaped_attr_val = String::from_utf8_lossy(&x).to_string();
+            let unescaped: Cow<str> = if trimmed.contains('&') {
+                let x = escapist::unescape_html(trimmed.as_bytes());
+                Cow::Owned(String::from_utf8_lossy(&x).into_owned())
+            } else {
+                Cow::Borrowed(trimmed)
+            };
+            // ...and strip what a browser would strip from a URL, before its protocol is checked;
+            // attributes holding several URLs are normalized one URL at a time, further down
+            let url_list = UrlList::of(&tag_name, attr_name, element);
+            let is_url = crate::url::is_url_attribute(attr_name)
+                || element_sanitizer.is_some_and(|sanitizer| {
+                    sanitizer
+                        .protocol_sanitizers
+                        .contains_key(attr_name.as_str())
+                });
+            let mut unescaped_attr_val: Cow<str> = if is_url && url_list.is_none() {
+                crate::url::normalize(&unescaped)
+            } else {
+                Cow::Borrowed(&unescaped)
+            };
 
-            let should_keep_attrubute = match Self::should_keep_attribute(
-                &binding,
+            let mut should_keep_attrubute = match Self::should_keep_attribute(
+                binding,
                 element,
-                &element_sanitizer,
+                element_sanitizer,
+                foreign_content,
+                url_list,
                 attr_name,
                 &unescaped_attr_val,
             ) {
                 Ok(should_keep) => should_keep,
                 Err(e) => {
-                    return Err(e);
+                    return Err(e.into());
                 }
             };
 
+            // a link is judged by where it leads, before any rewriting
+            if let Some(link_policy) = link_policy {
+                if attr_name == "href" && should_keep_attrubute {
+                    is_external_link = link_policy.is_external(&unescaped_attr_val);
+                }
+            }
+
+            // URLs that pass their protocol check are then resolved and rewritten, if configured
+            let url_rewriting = &binding.url_rewriting;
+            if should_keep_attrubute {
+                if let Some(url_list) = url_list {
+                    let protocols =
+                        Self::url_list_protocols(element_sanitizer, url_list, attr_name);
+                    let filtered = url_list.filter(&unescaped_attr_val, |url| {
+                        if !Self::is_allowed_url(protocols, url) {
+                            return Ok(None);
+                        }
+                        url_rewriting
+                            .rewrite(&tag_name, attr_name, url)
+                            .map(|url| url.map(Cow::into_owned))
+                    });
+                    match filtered.map_err(UrlRewriting::raised)? {
+                        Some(filtered) => unescaped_attr_val = Cow::Owned(filtered),
+                        None => should_keep_attrubute = false,
+                    }
+                } else if is_url && !url_rewriting.is_empty() {
+                    let rewritten = url_rewriting
+                        .rewrite(&tag_name, attr_name, &unescaped_attr_val)
+                        .map_err(UrlRewriting::raised)?
+                        .map(Cow::into_owned);
+                    match rewritten {
+                        Some(rewritten) => unescaped_attr_val = Cow::Owned(rewritten),
+                        None => should_keep_attrubute = false,
+                    }
+                }
+            }
+
+            // ids and names, and whatever points at them, are prefixed, so that they can't
+            // clobber globals
+            if should_keep_attrubute {
+                if let Some(id_prefix) = &binding.id_prefix {
+                    if let Some(prefixed) =
+                        id_prefix.prefix(namespace, &tag_name, attr_name, &unescaped_attr_val)
+                    {
+                        unescaped_attr_val = Cow::Owned(prefixed);
+                    }
+                }
+            }
+
             if !should_keep_attrubute {
                 element.remove_attribute(attr_name);
             } else {
                 // Prevent the use of `<meta>` elements that set a charset other than UTF-8,
                 // since output is always UTF-8.
-                if crate::tags::Tag::is_meta(tag) {
-                    if attr_name == "charset" && unescaped_attr_val != "utf-8" {
-                        match element.set_attribute(attr_name, "utf-8") {
-                            Ok(_) => {}
-                            Err(err) => {
-                                return Err(err);
-                            }
-                        }
+                if crate::tags::Tag::is_meta(tag) && attr_name == "charset" {
+                    if unescaped_attr_val != "utf-8" {
+                        element.set_attribute(attr_name, "utf-8")?;
                     }
                 } else if !unescaped_attr_val.is_empty() {
-                    let mut buf = String::new();
+                    buf.clear();
                     // ...then, escape any special characters, for security
                     if attr_name == "href" {
-                        escapist::escape_href(&mut buf, unescaped_attr_val.as_str()).unwrap();
+                        escapist::escape_href(&mut buf, &unescaped_attr_val).unwrap();
                     } else {
-                        escapist::escape_html(&mut buf, unescaped_attr_val.as_str()).unwrap();
+                        escapist::escape_html(&mut buf, &unescaped_attr_val).unwrap();
                     };
 
-                    match element.set_attribute(attr_name, &buf) {
-                        Ok(_) => {}
-                        Err(err) => {
-                            return Err(err);
-                        }
-                    }
+                    element.set_attribute(attr_name, &buf)?;
                 }
             }
         }
 
-        let required = &element_sanitizer.required_attrs;
-        if required.contains(&"*".to_string()) {
-            return Ok(());
-        }
-        for attr in element.attributes().iter() {
-            let attr_name = &attr.name();
-            if required.contains(attr_name) {
-                return Ok(());
+        if let Some(link_policy) = link_policy {
+            if is_external_link && element.has_attribute("href") {
+                link_policy.apply(element)?;
             }
         }
 
@@ -490,31 +1340,39 @@ impl SelmaSanitizer {
     }
 
     fn should_keep_attribute(
-        binding: &Sanitizer,
+        binding: &CompiledRules,
         element: &mut Element,
-        element_sanitizer: &ElementSanitizer,
-        attr_name: &String,
+        element_sanitizer: Option<&ElementSanitizer>,
+        foreign_content: Option<&ForeignContentRules>,
+        url_list: Option<UrlList>,
+        attr_name: &str,
         attr_val: &str,
     ) -> Result<bool, AttributeNameError> {
-        let mut allowed: bool = false;
-        let element_allowed_attrs = element_sanitizer.allowed_attrs.contains(attr_name);
-        let sanitizer_allowed_attrs = binding.allowed_attrs.contains(attr_name);
-
-        if element_allowed_attrs {
-            allowed = true;
-        }
-
-        if !allowed && sanitizer_allowed_attrs {
-            allowed = true;
-        }
+        let allowed_attrs =
+            foreign_content.map_or(&binding.allowed_attrs, |rules| &rules.allowed_attrs);
+        let attr_id = binding.attribute_names.id_of(attr_name);
+        let allowed = allowed_attrs.contains(attr_id, attr_name)
+            || element_sanitizer
+                .is_some_and(|sanitizer| sanitizer.allowed_attrs.contains(attr_id, attr_name));
 
         if !allowed {
             return Ok(false);
         }
 
-        let protocol_sanitizer_values = element_sanitizer.protocol_sanitizers.get(attr_name);
+        let protocol_sanitizer_values =
+            element_sanitizer.and_then(|sanitizer| sanitizer.protocol_sanitizers.get(attr_name));
         match protocol_sanitizer_values {
+            // each of its URLs is checked on its own, by the caller
+            _ if url_list.is_some() => {}
             None => {
+                let foreign = foreign_content.is_some();
+                // SVG links can run `javascript:` without a `://`, so unless told otherwise,
+                // they may only point within the document
+                let is_foreign_link = foreign && (attr_name == "href" || attr_name == "xlink:href");
+                if is_foreign_link && !attr_val.is_empty() && !attr_val.starts_with('#') {
+                    return Ok(false);
+                }
+
                 // has a protocol, but no sanitization list
                 if !attr_val.is_empty() && Self::has_protocol(attr_val) {
                     return Ok(false);
@@ -542,71 +1400,77 @@ impl SelmaSanitizer {
         Ok(true)
     }
 
-    fn has_protocol(attr_val: &str) -> bool {
-        attr_val.contains("://")
+    // The `protocols` for the URLs in a `UrlList` attribute: its own, or those of the attribute it
+    // falls back to.
+    fn url_list_protocols<'s>(
+        element_sanitizer: Option<&'s ElementSanitizer>,
+        url_list: UrlList,
+        attr_name: &str,
+    ) -> Option<&'s [String]> {
+        let protocol_sanitizers = &element_sanitizer?.protocol_sanitizers;
+
+        protocol_sanitizers
+            .get(attr_name)
+            .or_else(|| protocol_sanitizers.get(url_list.fallback_attribute()?))
+            .map(Vec::as_slice)
     }
 
-    fn has_allowed_protocol(protocols_allowed: &[String], attr_val: &str) -> bool {
-        if protocols_allowed.contains(&"all".to_string()) {
-            return true;
+    // Without a protocol list, only relative URLs are allowed.
+    fn is_allowed_url(protocols_allowed: Option<&[String]>, url: &str) -> bool {
+        match protocols_allowed {
+            Some(protocols_allowed) => Self::has_allowed_protocol(protocols_allowed, url),
+            None => crate::url::scheme(url).is_none(),
         }
+    }
 
-        // FIXME: is there a more idiomatic way to do this?
-        let mut pos: usize = 0;
-        let mut chars = attr_val.chars();
-        let len = attr_val.len();
-
-        for (i, c) in attr_val.chars().enumerate() {
-            if c != ':' && c != '/' && c != '#' && pos + 1 < len {
-                pos = i + 1;
-            } else {
-                break;
-            }
-        }
+    fn has_protocol(attr_val: &str) -> bool {
+        attr_val.contains("://")
+    }
 
-        let char = chars.nth(pos).unwrap();
+    fn has_allowed_protocol(protocols_allowed: &[String], url: &str) -> bool {
+        let allows = |protocol: &str| protocols_allowed.iter().any(|allowed| allowed == protocol);
 
-        if char == '/' {
-            return protocols_allowed.contains(&"/".to_string());
+        if allows("all") {
+            return true;
         }
 
-        if char == '#' {
-            return protocols_allowed.contains(&"#".to_string());
+        if let Some(scheme) = crate::url::scheme(url) {
+            return allows(&scheme);
         }
 
-        // Allow protocol name to be case-insensitive
-        let protocol = attr_val[0..pos].to_lowercase();
-
-        protocols_allowed.contains(&protocol.to_lowercase())
+        // relative URLs, allowed by `:relative`, are told apart by whichever comes first
+        match url.find(['/', '#']).map(|i| &url[i..=i]) {
+            Some(delimiter) => allows(delimiter),
+            None => false,
+        }
     }
 
     fn sanitize_class_attribute(
-        binding: &Sanitizer,
+        binding: &CompiledRules,
         element: &mut Element,
-        element_sanitizer: &ElementSanitizer,
+        element_sanitizer: Option<&ElementSanitizer>,
         attr_name: &str,
         attr_val: &str,
     ) -> Result<bool, lol_html::errors::AttributeNameError> {
         let allowed_global = &binding.allowed_classes;
 
-        let mut valid_classes: Vec<String> = vec![];
+        let mut valid_classes: Vec<&str> = vec![];
 
-        let allowed_local = &element_sanitizer.allowed_classes;
+        let allowed_local = element_sanitizer.map(|sanitizer| &sanitizer.allowed_classes);
 
         // No class filters, so everything goes through
-        if allowed_global.is_empty() && allowed_local.is_empty() {
+        if allowed_global.is_empty() && allowed_local.map_or(true, |classes| classes.is_empty()) {
             return Ok(true);
         }
 
         let attr_value = attr_val.trim_start();
-        attr_value
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .for_each(|class| {
-                if allowed_global.contains(&class) || allowed_local.contains(&class) {
-                    valid_classes.push(class);
-                }
-            });
+        attr_value.split_whitespace().for_each(|class| {
+            if allowed_global.contains(class)
+                || allowed_local.is_some_and(|classes| classes.contains(class))
+            {
+                valid_classes.push(class);
+            }
+        });
 
         if valid_classes.is_empty() {
             return Ok(false);
@@ -619,20 +1483,30 @@ impl SelmaSanitizer {
     }
 
     pub fn allow_element(&self, element: &mut Element) -> bool {
+        let namespace = Namespace::of(element);
+        if let Some(rules) = self.rules.foreign_content(namespace) {
+            return !rules.allows(namespace, element.tag_name());
+        }
+
         let tag = crate::tags::Tag::tag_from_element(element);
-        let flags: u8 = self.0.borrow().flags[tag.index];
+        let flags: u8 = self.flags_for(tag, element);
 
         (flags & Self::SELMA_SANITIZER_ALLOW) == 0
     }
 
     pub fn try_remove_element(&self, element: &mut Element) -> bool {
+        self.rename_element(element);
+
         let tag = crate::tags::Tag::tag_from_element(element);
-        let flags: u8 = self.0.borrow().flags[tag.index];
+        let flags: u8 = self.flags_for(tag, element);
 
         let should_remove = !element.removed() && self.allow_element(element);
 
         if should_remove {
-            if crate::tags::Tag::has_text_content(tag) {
+            let foreign = self.rules.foreign_content(Namespace::of(element)).is_some();
+
+            // nothing removed from foreign content is safe to leave lying around as HTML
+            if foreign || crate::tags::Tag::has_text_content(tag) {
                 Self::remove_element(
                     element,
                     tag.self_closing,
@@ -646,7 +1520,7 @@ impl SelmaSanitizer {
         } else {
             // anything in <iframe> must be removed, if it's kept
             if crate::tags::Tag::is_iframe(tag) {
-                if self.0.borrow().flags[tag.index] != 0 {
+                if self.rules.flags[tag.index] != 0 {
                     element.set_inner_content(" ", ContentType::Text);
                 } else {
                     element.set_inner_content("", ContentType::Text);
@@ -657,6 +1531,29 @@ impl SelmaSanitizer {
         should_remove
     }
 
+    /// Renames an HTML element according to `rename_elements`, then shifts it by
+    /// `headings[:shift]` if it's a heading. Its end tag follows along.
+    fn rename_element(&self, element: &mut Element) {
+        let renamed_elements = &self.rules.renamed_elements;
+        let headings = &self.rules.headings;
+        if (renamed_elements.is_empty() && !headings.shifts())
+            || Namespace::of(element) != Namespace::Html
+        {
+            return;
+        }
+
+        let mut tag_name = element.tag_name();
+        tag_name.make_ascii_lowercase();
+        if let Some(new_name) = renamed_elements.get(&tag_name) {
+            // new names were checked when the config was compiled
+            let _ = element.set_tag_name(new_name);
+            tag_name.clone_from(new_name);
+        }
+        if let Some(new_name) = headings.shifted(&tag_name) {
+            let _ = element.set_tag_name(&new_name);
+        }
+    }
+
     fn remove_element(element: &mut Element, self_closing: bool, flags: u8) {
         let wrap_whitespace = (flags & Self::SELMA_SANITIZER_WRAP_WHITESPACE) != 0;
         let remove_contents = (flags & Self::SELMA_SANITIZER_REMOVE_CONTENTS) != 0;
@@ -714,8 +1611,32 @@ pub fn init(m_selma: RModule) -> Result<(), magnus::Error> {
         .define_class("Sanitizer", ruby.class_object())
         .expect("cannot define class Selma::Sanitizer");
 
-    c_sanitizer.define_singleton_method("new", function!(SelmaSanitizer::new, -1))?;
+    // the compiled rules never change, so a sanitizer can be used from any Ractor; nothing
+    // else in Selma is declared Ractor-safe
+    unsafe { rb_sys::rb_ext_ractor_safe(true) };
+
+    c_sanitizer.define_singleton_method("new", function!(SelmaSanitizer::rb_new, -1))?;
     c_sanitizer.define_method("config", method!(SelmaSanitizer::get_config, 0))?;
+    c_sanitizer.define_method("sanitize", method!(SelmaSanitizer::sanitize, 1))?;
+    c_sanitizer.define_method(
+        "allowed_elements",
+        method!(SelmaSanitizer::allowed_elements, 0),
+    )?;
+    c_sanitizer.define_method(
+        "allowed_attributes",
+        method!(SelmaSanitizer::allowed_attributes, -1),
+    )?;
+    c_sanitizer.define_method(
+        "allowed_protocols",
+        method!(SelmaSanitizer::allowed_protocols, 2),
+    )?;
+    c_sanitizer.define_method(
+        "remove_contents_elements",
+        method!(SelmaSanitizer::remove_contents_elements, 0),
+    )?;
+    c_sanitizer.define_method("allowed?", method!(SelmaSanitizer::is_allowed, 1))?;
+
+    unsafe { rb_sys::rb_ext_ractor_safe(false) };
 
     Ok(())
 }
@@ -0,0 +1,317 @@
+use magnus::{
+    r_hash::ForEach, value::ReprValue, Error, RArray, RHash, RString, Ruby, Symbol, Value,
+};
+
+// every key `Selma::Sanitizer` reads from its config
+const KEYS: &[&str] = &[
+    "allow_comments",
+    "allow_doctype",
+    "attributes",
+    "base_url",
+    "clobber_protection",
+    "custom_elements",
+    "elements",
+    "escape_tagfilter",
+    "foreign_content",
+    "headings",
+    "id_prefix",
+    "link_policy",
+    "protocols",
+    "remove_contents",
+    "rename_elements",
+    "strict",
+    "url_rewriter",
+    "whitespace_elements",
+];
+
+const FOREIGN_CONTENT_NAMESPACES: &[&str] = &["math", "svg"];
+
+const FOREIGN_CONTENT_KEYS: &[&str] = &["attributes", "elements", "protocols"];
+
+const HEADINGS_KEYS: &[&str] = &["id_prefix", "ids", "shift"];
+
+const LINK_POLICY_KEYS: &[&str] = &["internal_hosts", "rel", "target"];
+
+/// An element or attribute name from a config, given as a String or a Symbol, lowercased to
+/// match what `lol_html` reports.
+pub fn config_name(value: Value) -> Option<String> {
+    let name = match Symbol::from_value(value) {
+        Some(symbol) => symbol.name().ok()?.to_string(),
+        None => RString::from_value(value)?.to_string().ok()?,
+    };
+
+    Some(name.to_ascii_lowercase())
+}
+
+fn is_name(value: Value) -> bool {
+    Symbol::from_value(value).is_some() || RString::from_value(value).is_some()
+}
+
+/// Checks a sanitizer config up front, so that a typo like `remove_content:` raises rather
+/// than silently turning a protection off. `custom_elements`, `base_url`, `url_rewriter`,
+/// `id_prefix` and the values in `link_policy` and `headings` are checked when they're read.
+pub fn validate(config: RHash) -> Result<(), Error> {
+    config.foreach(|key: Value, value: Value| {
+        let key = key_name(key, "", KEYS)?;
+        match key.as_str() {
+            "allow_comments" | "allow_doctype" | "clobber_protection" | "escape_tagfilter"
+            | "strict" => validate_bool(&key, value)?,
+            "elements" | "whitespace_elements" => validate_names(&key, value)?,
+            "remove_contents" => {
+                if !is_bool(value) {
+                    validate_names(&key, value)?;
+                }
+            }
+            "attributes" => validate_attributes(&key, value)?,
+            "protocols" => validate_protocols(&key, value)?,
+            "foreign_content" => validate_foreign_content(&key, value)?,
+            "link_policy" if !value.is_nil() => validate_keys(&key, value, LINK_POLICY_KEYS)?,
+            "headings" if !value.is_nil() => validate_keys(&key, value, HEADINGS_KEYS)?,
+            "rename_elements" if !value.is_nil() => validate_renames(&key, value)?,
+            _ => {}
+        }
+
+        Ok(ForEach::Continue)
+    })
+}
+
+fn validate_foreign_content(path: &str, value: Value) -> Result<(), Error> {
+    let foreign_content = expect_hash(path, value)?;
+
+    foreign_content.foreach(|namespace: Value, rules: Value| {
+        let namespace = key_name(namespace, path, FOREIGN_CONTENT_NAMESPACES)?;
+        let path = format!("{path}[:{namespace}]");
+        let rules = expect_hash(&path, rules)?;
+
+        rules.foreach(|key: Value, value: Value| {
+            let key = key_name(key, &path, FOREIGN_CONTENT_KEYS)?;
+            let path = format!("{path}[:{key}]");
+            match key.as_str() {
+                "elements" => validate_names(&path, value)?,
+                "attributes" => validate_attributes(&path, value)?,
+                "protocols" => validate_protocols(&path, value)?,
+                _ => {}
+            }
+
+            Ok(ForEach::Continue)
+        })?;
+
+        Ok(ForEach::Continue)
+    })
+}
+
+// the values themselves are checked when they're read
+fn validate_keys(path: &str, value: Value, known: &[&str]) -> Result<(), Error> {
+    expect_hash(path, value)?.foreach(|key: Value, _: Value| {
+        key_name(key, path, known)?;
+        Ok(ForEach::Continue)
+    })
+}
+
+fn validate_renames(path: &str, value: Value) -> Result<(), Error> {
+    expect_hash(path, value)?.foreach(|from: Value, to: Value| {
+        if !is_name(from) || !is_name(to) {
+            return Err(invalid(format!(
+                "`{path}` must map element names to element names; found `{} => {}`",
+                from.inspect(),
+                to.inspect()
+            )));
+        }
+
+        Ok(ForEach::Continue)
+    })
+}
+
+fn validate_attributes(path: &str, value: Value) -> Result<(), Error> {
+    let attributes = expect_hash(path, value)?;
+
+    attributes.foreach(|element: Value, names: Value| {
+        // `:all` is just another name here
+        if !is_name(element) {
+            return Err(invalid(format!(
+                "`{path}` keys must be element names, or `:all`; found `{}`",
+                element.inspect()
+            )));
+        }
+
+        let path = format!("{path}[{}]", element.inspect());
+        validate_names(&path, names)?;
+
+        // `:data` allows every `data-*` attribute; the String would look like it does too
+        if let Some(names) = RArray::from_value(names) {
+            for name in names.into_iter() {
+                if RString::from_value(name).is_some()
+                    && config_name(name).as_deref() == Some("data")
+                {
+                    return Err(invalid(format!(
+                        "`{path}` lists the String \"data\"; use the Symbol `:data` to allow every `data-*` attribute"
+                    )));
+                }
+            }
+        }
+
+        Ok(ForEach::Continue)
+    })
+}
+
+fn validate_protocols(path: &str, value: Value) -> Result<(), Error> {
+    let protocols = expect_hash(path, value)?;
+
+    protocols.foreach(|element: Value, attributes: Value| {
+        if !is_name(element) {
+            return Err(invalid(format!(
+                "`{path}` keys must be element names; found `{}`",
+                element.inspect()
+            )));
+        }
+        let path = format!("{path}[{}]", element.inspect());
+        let attributes = expect_hash(&path, attributes)?;
+
+        attributes.foreach(|attribute: Value, protocols: Value| {
+            if !is_name(attribute) {
+                return Err(invalid(format!(
+                    "`{path}` keys must be attribute names; found `{}`",
+                    attribute.inspect()
+                )));
+            }
+            // arrays are checked further (`:all` inside one, and so on) as they're read
+            if let Some(protocols) = RArray::from_value(protocols) {
+                for protocol in protocols.into_iter() {
+                    if !is_name(protocol) {
+                        return Err(invalid(format!(
+                            "`{path}[{}]` must only contain protocols as Strings, or `:relative`; found `{}`",
+                            attribute.inspect(),
+                            protocol.inspect()
+                        )));
+                    }
+                }
+            }
+
+            Ok(ForEach::Continue)
+        })?;
+
+        Ok(ForEach::Continue)
+    })
+}
+
+/// `value` must be an Array of names, as Strings or Symbols.
+fn validate_names(path: &str, value: Value) -> Result<(), Error> {
+    let Some(names) = RArray::from_value(value) else {
+        return Err(invalid(format!(
+            "`{path}` must be an Array of Strings or Symbols, not `{}`",
+            value.inspect()
+        )));
+    };
+
+    for name in names.into_iter() {
+        if !is_name(name) {
+            return Err(invalid(format!(
+                "`{path}` must only contain Strings or Symbols; found `{}`",
+                name.inspect()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_bool(path: &str, value: Value) -> Result<(), Error> {
+    if is_bool(value) {
+        Ok(())
+    } else {
+        Err(invalid(format!(
+            "`{path}` must be `true` or `false`, not `{}`",
+            value.inspect()
+        )))
+    }
+}
+
+fn is_bool(value: Value) -> bool {
+    let ruby = Ruby::get().unwrap();
+    value.is_kind_of(ruby.class_true_class()) || value.is_kind_of(ruby.class_false_class())
+}
+
+fn expect_hash(path: &str, value: Value) -> Result<RHash, Error> {
+    RHash::from_value(value).ok_or_else(|| {
+        invalid(format!(
+            "`{path}` must be a Hash, not `{}`",
+            value.inspect()
+        ))
+    })
+}
+
+/// The name of `key`, which must be one of the `known` Symbols.
+fn key_name(key: Value, path: &str, known: &[&str]) -> Result<String, Error> {
+    let location = if path.is_empty() {
+        "sanitizer config".to_string()
+    } else {
+        format!("`{path}`")
+    };
+
+    let name = match Symbol::from_value(key) {
+        Some(symbol) => symbol.name()?.to_string(),
+        None => match RString::from_value(key) {
+            Some(string) => {
+                let name = string.to_string()?;
+                let hint = if known.contains(&name.as_str()) {
+                    format!(" Did you mean `:{name}`?")
+                } else {
+                    String::new()
+                };
+                return Err(invalid(format!(
+                    "{location} keys must be Symbols; found `{}`.{hint}",
+                    key.inspect()
+                )));
+            }
+            None => {
+                return Err(invalid(format!(
+                    "{location} keys must be Symbols; found `{}`",
+                    key.inspect()
+                )));
+            }
+        },
+    };
+
+    if known.contains(&name.as_str()) {
+        return Ok(name);
+    }
+
+    let hint = match did_you_mean(&name, known) {
+        Some(suggestion) => format!(" Did you mean `:{suggestion}`?"),
+        None => String::new(),
+    };
+    Err(invalid(format!(
+        "unknown key `:{name}` in {location}.{hint}"
+    )))
+}
+
+/// The closest of `candidates` to a misspelled `word`, if any is close enough to be a likely typo.
+fn did_you_mean<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(word, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= (word.len().max(candidate.len()) / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance, counting an insertion, deletion or substitution as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+pub fn invalid(message: String) -> Error {
+    Error::new(Ruby::get().unwrap().exception_arg_error(), message)
+}
@@ -1,58 +1,108 @@
-use magnus::{function, scan_args, Error, Module, Object, RModule, Ruby, Value};
+use crate::ancestors::AncestorSelector;
+use magnus::{
+    function, scan_args, value::ReprValue, Error, Module, Object, RArray, RModule, RString, Ruby,
+    TryConvert, Value,
+};
+
+/// One of the `match_element` selectors. `lol_html` knows nothing of namespaces, so a selector
+/// like `svg|a` is handed to it as `a`, and whatever that matches is checked against the
+/// namespaced original.
+#[derive(Clone, Debug)]
+pub struct ElementSelector {
+    css: String,
+    lol_html_css: String,
+    namespaced: Option<AncestorSelector>,
+}
+
+impl ElementSelector {
+    fn new(css: String) -> Self {
+        match AncestorSelector::parse(&css) {
+            Ok(selector) if selector.is_namespaced() => Self {
+                lol_html_css: selector.to_css_without_namespaces(),
+                css,
+                namespaced: Some(selector),
+            },
+            _ => Self {
+                lol_html_css: css.clone(),
+                css,
+                namespaced: None,
+            },
+        }
+    }
+
+    /// The selector as it was given.
+    pub fn css(&self) -> &str {
+        &self.css
+    }
+
+    /// The selector `lol_html` should match elements with.
+    pub fn lol_html_css(&self) -> &str {
+        &self.lol_html_css
+    }
+
+    /// The selector, if it's namespaced, which whatever `lol_html` matches must be checked
+    /// against too.
+    pub fn namespaced(&self) -> Option<&AncestorSelector> {
+        self.namespaced.as_ref()
+    }
+}
 
 #[derive(Clone, Debug)]
 #[magnus::wrap(class = "Selma::Selector")]
 pub struct SelmaSelector {
-    match_element: Option<String>,
-    match_text_within: Option<String>,
-    ignore_text_within: Option<Vec<String>>,
+    match_element: Vec<ElementSelector>,
+    match_text_within: Vec<String>,
+    ignore_text_within: Option<AncestorSelector>,
 }
 
-type SelectorMatches = (Option<String>, Option<String>, Option<Vec<String>>);
+type SelectorMatches = (Option<Value>, Option<Value>, Option<Value>);
 
 impl SelmaSelector {
     fn new(args: &[Value]) -> Result<Self, Error> {
-        let (match_element, match_text_within, rb_ignore_text_within) =
+        let (rb_match_element, rb_match_text_within, rb_ignore_text_within) =
             Self::scan_parse_args(args)?;
         let ruby = Ruby::get().unwrap();
 
-        if match_element.is_none() && match_text_within.is_none() {
+        if rb_match_element.is_none() && rb_match_text_within.is_none() {
             return Err(Error::new(
                 ruby.exception_arg_error(),
                 "Neither `match_element` nor `match_text_within` option given",
             ));
         }
 
-        // FIXME: not excited about this double parse work (`element!` does it too),
-        // but at least we can bail ASAP if the CSS is invalid
-        if let Some(css) = &match_element {
-            if css.parse::<lol_html::Selector>().is_err() {
-                return Err(Error::new(
-                    ruby.exception_arg_error(),
-                    format!("Could not parse `match_element` (`{css:?}`) as valid CSS"),
-                ));
-            }
-        }
-
-        if let Some(css) = &match_text_within {
-            if css.parse::<lol_html::Selector>().is_err() {
-                return Err(Error::new(
-                    ruby.exception_arg_error(),
-                    format!("Could not parse `match_text_within` (`{css:?}`) as valid CSS",),
-                ));
-            }
+        let match_element = Self::parse_css(&ruby, "match_element", rb_match_element)?;
+        let match_text_within = Self::parse_css(&ruby, "match_text_within", rb_match_text_within)?;
+        // text chunks aren't elements, so there'd be nothing to check a namespace against
+        if match_text_within.iter().any(|s| s.namespaced.is_some()) {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "Namespace prefixes are only supported in `match_element`",
+            ));
         }
+        let match_text_within = match_text_within.into_iter().map(|s| s.css).collect();
 
         let ignore_text_within = match rb_ignore_text_within {
             None => None,
+            // nothing to ignore
+            Some(rb_ignore_text_within)
+                if RArray::from_value(rb_ignore_text_within).is_some_and(|a| a.is_empty()) =>
+            {
+                None
+            }
             Some(rb_ignore_text_within) => {
-                let mut ignore_text_within = vec![];
-                rb_ignore_text_within.iter().for_each(|i| {
-                    // TODO: test this against malice
-                    let ignore_text_within_tag_name = i.to_string();
-                    ignore_text_within.push(ignore_text_within_tag_name);
-                });
-                Some(ignore_text_within)
+                let mut selectors = vec![];
+                for css in Self::css_strings(&ruby, "ignore_text_within", rb_ignore_text_within)? {
+                    match AncestorSelector::parse(&css) {
+                        Ok(selector) => selectors.push(selector),
+                        Err(err) => {
+                            return Err(Error::new(
+                                ruby.exception_arg_error(),
+                                format!("Could not parse `ignore_text_within` (`{css:?}`): {err}"),
+                            ));
+                        }
+                    }
+                }
+                Some(AncestorSelector::concat(selectors))
             }
         };
 
@@ -72,12 +122,7 @@ impl SelmaSelector {
         let _: () = args.trailing;
         let _: () = args.block;
 
-        let kw = scan_args::get_kwargs::<
-            _,
-            (),
-            (Option<String>, Option<String>, Option<Vec<String>>),
-            (),
-        >(
+        let kw = scan_args::get_kwargs::<_, (), (Option<Value>, Option<Value>, Option<Value>), ()>(
             args.keywords,
             &[],
             &["match_element", "match_text_within", "ignore_text_within"],
@@ -87,16 +132,73 @@ impl SelmaSelector {
         Ok((match_element, match_text_within, rb_ignore_text_within))
     }
 
-    pub fn match_element(&self) -> Option<&str> {
-        self.match_element.as_deref()
+    /// Accepts either a single CSS selector, or an array of them.
+    fn css_strings(ruby: &Ruby, name: &str, value: Value) -> Result<Vec<String>, Error> {
+        if RString::from_value(value).is_some() {
+            Ok(vec![String::try_convert(value)?])
+        } else if RArray::from_value(value).is_some() {
+            let selectors = Vec::<String>::try_convert(value)?;
+            if selectors.is_empty() {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    format!("`{name}` must not be an empty array"),
+                ));
+            }
+            Ok(selectors)
+        } else {
+            Err(Error::new(
+                ruby.exception_type_error(),
+                format!(
+                    "`{name}` must be a String or an Array of Strings, not {}",
+                    unsafe { value.classname() }
+                ),
+            ))
+        }
+    }
+
+    fn parse_css(
+        ruby: &Ruby,
+        name: &str,
+        value: Option<Value>,
+    ) -> Result<Vec<ElementSelector>, Error> {
+        let Some(value) = value else {
+            return Ok(vec![]);
+        };
+        let selectors = Self::css_strings(ruby, name, value)?;
+
+        // FIXME: not excited about this double parse work (`element!` does it too),
+        // but at least we can bail ASAP if the CSS is invalid
+        let selectors: Vec<ElementSelector> =
+            selectors.into_iter().map(ElementSelector::new).collect();
+        for selector in selectors.iter() {
+            if selector
+                .lol_html_css()
+                .parse::<lol_html::Selector>()
+                .is_err()
+            {
+                return Err(Error::new(
+                    ruby.exception_arg_error(),
+                    format!(
+                        "Could not parse `{name}` (`{:?}`) as valid CSS",
+                        selector.css()
+                    ),
+                ));
+            }
+        }
+
+        Ok(selectors)
+    }
+
+    pub fn match_element(&self) -> &[ElementSelector] {
+        &self.match_element
     }
 
-    pub fn match_text_within(&self) -> Option<&str> {
-        self.match_text_within.as_deref()
+    pub fn match_text_within(&self) -> &[String] {
+        &self.match_text_within
     }
 
-    pub fn ignore_text_within(&self) -> Option<&[String]> {
-        self.ignore_text_within.as_deref()
+    pub fn ignore_text_within(&self) -> Option<&AncestorSelector> {
+        self.ignore_text_within.as_ref()
     }
 }
 
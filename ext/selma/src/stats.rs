@@ -0,0 +1,56 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use magnus::{value::ReprValue, RHash, RModule, Ruby, Value};
+
+/// Call count and cumulative wall-clock time for one piece of a rewrite.
+#[derive(Clone, Debug, Default)]
+pub struct Timing {
+    calls: Cell<usize>,
+    elapsed: Cell<Duration>,
+}
+
+impl Timing {
+    pub fn time<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(start.elapsed());
+        result
+    }
+
+    pub fn record(&self, elapsed: Duration) {
+        self.calls.set(self.calls.get() + 1);
+        self.elapsed.set(self.elapsed.get() + elapsed);
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.get()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+
+    pub fn to_hash(&self, ruby: &Ruby) -> Result<RHash, magnus::Error> {
+        let hash = ruby.hash_new();
+        hash.aset(ruby.to_symbol("calls"), self.calls())?;
+        hash.aset(ruby.to_symbol("elapsed"), self.elapsed().as_secs_f64())?;
+        Ok(hash)
+    }
+}
+
+/// The callback registered with `Selma.instrument=`, if any.
+pub fn instrumenter(ruby: &Ruby) -> Result<Option<Value>, magnus::Error> {
+    let selma = ruby.class_object().const_get::<_, RModule>("Selma")?;
+    let callback: Value = selma.funcall("instrument", ())?;
+
+    Ok((!callback.is_nil()).then_some(callback))
+}
+
+/// Reports `event` to the `Selma.instrument` callback, as `callback.call(event, payload)`.
+pub fn instrument(callback: Value, event: &str, payload: RHash) -> Result<(), magnus::Error> {
+    callback.funcall::<_, _, Value>("call", (event, payload))?;
+    Ok(())
+}
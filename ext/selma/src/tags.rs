@@ -1,7 +1,9 @@
+use std::collections::HashMap;
+
 use enum_iterator::{all, Sequence};
 use lol_html::html_content::Element;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Tag {
     pub name: &'static str,
     pub index: usize,
@@ -184,6 +186,36 @@ impl Tag {
         tag.index == HTMLTag::IFRAME as usize
     }
 
+    /// Is this a tag Selma doesn't know about (e.g. a custom element)?
+    pub fn is_unknown(tag: Tag) -> bool {
+        tag.index == HTMLTag::UNKNOWN as usize
+    }
+
+    /// Whether `tag_name` is a valid custom element name, e.g. `my-widget`.
+    /// See <https://html.spec.whatwg.org/multipage/custom-elements.html#valid-custom-element-name>.
+    pub fn is_valid_custom_element_name(tag_name: &str) -> bool {
+        const RESERVED: [&str; 8] = [
+            "annotation-xml",
+            "color-profile",
+            "font-face",
+            "font-face-src",
+            "font-face-uri",
+            "font-face-format",
+            "font-face-name",
+            "missing-glyph",
+        ];
+
+        tag_name.starts_with(|c: char| c.is_ascii_lowercase())
+            && tag_name.contains('-')
+            && tag_name.chars().all(|c| {
+                c.is_ascii_lowercase()
+                    || c.is_ascii_digit()
+                    || matches!(c, '-' | '.' | '_')
+                    || !c.is_ascii()
+            })
+            && !RESERVED.contains(&tag_name)
+    }
+
     /// Is this tag a `<meta>`?
     pub fn is_meta(tag: Tag) -> bool {
         tag.index == HTMLTag::META as usize
@@ -1134,3 +1166,110 @@ impl Tag {
         }
     }
 }
+
+/// Hands out flag slots for tag names missing from the `Tag` table (custom elements and the
+/// like), so that they can be configured individually rather than sharing `UNKNOWN`'s slot.
+/// Slots are numbered after the known tags, starting at `Tag::TAG_COUNT`.
+#[derive(Clone, Debug, Default)]
+pub struct TagInterner {
+    indices: HashMap<String, usize>,
+}
+
+impl TagInterner {
+    /// The flag slot for `tag_name`, which is given one if it doesn't have it yet. Known tags
+    /// keep their `Tag` index.
+    pub fn intern(&mut self, tag_name: &str) -> usize {
+        let tag_name = tag_name.to_ascii_lowercase();
+        let tag = Tag::tag_from_tag_name(&tag_name);
+        if !Tag::is_unknown(tag) {
+            return tag.index;
+        }
+
+        let next_index = Tag::TAG_COUNT + self.indices.len();
+        *self.indices.entry(tag_name).or_insert(next_index)
+    }
+
+    /// The flag slot for an unknown (lowercase) `tag_name`, if it has been interned.
+    pub fn index_of(&self, tag_name: &str) -> Option<usize> {
+        self.indices.get(tag_name).copied()
+    }
+
+    /// Every interned name, with its flag slot.
+    pub fn names(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.indices
+            .iter()
+            .map(|(tag_name, index)| (tag_name.as_str(), *index))
+    }
+
+    /// The total number of flag slots needed, known tags included.
+    pub fn slot_count(&self) -> usize {
+        Tag::TAG_COUNT + self.indices.len()
+    }
+}
+
+/// The namespace an element was parsed into, as reported by `Element::namespace_uri`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Namespace {
+    Html,
+    Svg,
+    MathML,
+}
+
+impl Namespace {
+    pub const HTML_URI: &'static str = "http://www.w3.org/1999/xhtml";
+    pub const SVG_URI: &'static str = "http://www.w3.org/2000/svg";
+    pub const MATHML_URI: &'static str = "http://www.w3.org/1998/Math/MathML";
+
+    pub fn from_uri(uri: &str) -> Self {
+        match uri {
+            Self::SVG_URI => Self::Svg,
+            Self::MATHML_URI => Self::MathML,
+            _ => Self::Html,
+        }
+    }
+
+    pub fn of(element: &Element) -> Self {
+        Self::from_uri(element.namespace_uri())
+    }
+
+    /// The namespace for a CSS namespace prefix, as in `svg|title`.
+    pub fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "html" => Some(Self::Html),
+            "svg" => Some(Self::Svg),
+            "math" => Some(Self::MathML),
+            _ => None,
+        }
+    }
+
+    pub fn uri(&self) -> &'static str {
+        match self {
+            Self::Html => Self::HTML_URI,
+            Self::Svg => Self::SVG_URI,
+            Self::MathML => Self::MATHML_URI,
+        }
+    }
+
+    /// Whether `tag_name` (lowercase) is one of this namespace's HTML integration points which
+    /// the foreign content rules never allow, whatever the config says.
+    ///
+    /// The HTML spec parses content inside these as HTML: SVG's `<foreignObject>`, `<desc>` and
+    /// `<title>`, MathML's `<annotation-xml>` (when its `encoding` is `text/html` or
+    /// `application/xhtml+xml`), and, as text integration points, MathML's `<mi>`, `<mo>`,
+    /// `<mn>`, `<ms>` and `<mtext>`. `lol_html` reports whatever is parsed that way as HTML, so
+    /// it's judged by the HTML `elements` and `attributes` rather than the foreign content rules,
+    /// which is what makes `<desc>`, `<title>` and the MathML token elements safe to allow.
+    ///
+    /// The other two are refused regardless. `<foreignObject>` renders its HTML as part of the
+    /// image, so allowing it would let the HTML rules' elements be laid out anywhere the image
+    /// can reach. `<annotation-xml>` is only an integration point depending on its `encoding`,
+    /// which sanitizing can drop or change, so a browser could parse its contents in a different
+    /// namespace than the sanitizer did.
+    pub fn is_forbidden_integration_point(&self, tag_name: &str) -> bool {
+        match self {
+            Self::Html => false,
+            Self::Svg => tag_name == "foreignobject",
+            Self::MathML => tag_name == "annotation-xml",
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::{borrow::Cow, ops::Range};
+
+use lol_html::html_content::Element;
+
+/// Attributes whose values are URLs, and so are normalized before their protocol is checked.
+const URL_ATTRIBUTES: &[&str] = &[
+    "action",
+    "formaction",
+    "href",
+    "src",
+    "srcset",
+    "xlink:href",
+];
+
+pub fn is_url_attribute(attr_name: &str) -> bool {
+    URL_ATTRIBUTES.contains(&attr_name)
+}
+
+/// `value`, without what the WHATWG URL parser strips before it looks for a scheme: leading and
+/// trailing C0 controls and spaces, and every ASCII tab and newline. NULs are removed as well,
+/// so that `java\0script:` can't be read one way here and another in a browser.
+/// See <https://url.spec.whatwg.org/#concept-basic-url-parser>.
+pub fn normalize(value: &str) -> Cow<'_, str> {
+    let is_c0_control_or_space = |c: char| c <= ' ';
+    let is_stripped = |c: char| matches!(c, '\t' | '\n' | '\r' | '\0');
+
+    let trimmed = value.trim_matches(is_c0_control_or_space);
+    if trimmed.contains(is_stripped) {
+        Cow::Owned(trimmed.chars().filter(|c| !is_stripped(*c)).collect())
+    } else {
+        Cow::Borrowed(trimmed)
+    }
+}
+
+/// The scheme of a `normalize`d URL, lowercased, or `None` for a relative URL.
+///
+/// This is everything before the first `:`, unless a `/` or `#` comes first. That's looser than
+/// the WHATWG URL parser, which only accepts ASCII letters, digits, `+`, `-` and `.`, and full-width
+/// forms (like `ｊａｖａｓｃｒｉｐｔ：`) are read as their ASCII counterparts too: anything a browser
+/// might take for a scheme is checked as one.
+pub fn scheme(url: &str) -> Option<String> {
+    split_scheme(url).map(|(scheme, _)| scheme)
+}
+
+// The `scheme` of `url`, and whatever follows its `:`
+fn split_scheme(url: &str) -> Option<(String, &str)> {
+    let mut scheme = String::new();
+
+    for (i, c) in url.char_indices() {
+        match fold_full_width(c) {
+            ':' => return Some((scheme, &url[i + c.len_utf8()..])),
+            '/' | '#' => return None,
+            folded => scheme.extend(folded.to_lowercase()),
+        }
+    }
+
+    None
+}
+
+// U+FF01..=U+FF5E mirror `!`..=`~`
+fn fold_full_width(c: char) -> char {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+/// Attributes holding several URLs, or a URL inside some other syntax, whose URLs are checked one
+/// at a time rather than as a single value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UrlList {
+    /// `srcset`: comma-separated image candidates, each a URL and an optional descriptor.
+    Srcset,
+    /// `ping`: space-separated URLs.
+    Ping,
+    /// `content` of `<meta http-equiv="refresh">`: a delay, then optionally `url=` and a URL.
+    Refresh,
+}
+
+impl UrlList {
+    pub fn of(tag_name: &str, attr_name: &str, element: &Element) -> Option<UrlList> {
+        match attr_name {
+            "srcset" => Some(UrlList::Srcset),
+            "ping" => Some(UrlList::Ping),
+            "content" if tag_name == "meta" => element
+                .get_attribute("http-equiv")
+                .filter(|http_equiv| http_equiv.trim().eq_ignore_ascii_case("refresh"))
+                .map(|_| UrlList::Refresh),
+            _ => None,
+        }
+    }
+
+    /// The attribute whose `protocols` apply, when this one has none of its own: `srcset` follows
+    /// `src`, and `ping` follows `href`.
+    pub fn fallback_attribute(self) -> Option<&'static str> {
+        match self {
+            UrlList::Srcset => Some("src"),
+            UrlList::Ping => Some("href"),
+            UrlList::Refresh => None,
+        }
+    }
+
+    /// `value`, with each of its URLs replaced by what `keep` returns for it, or dropped when that's
+    /// `None`; `None` if nothing is left. URLs are `normalize`d before `keep` sees them.
+    pub fn filter<E>(
+        self,
+        value: &str,
+        mut keep: impl FnMut(&str) -> Result<Option<String>, E>,
+    ) -> Result<Option<String>, E> {
+        match self {
+            UrlList::Srcset => {
+                let mut candidates: Vec<String> = vec![];
+                for (url, descriptor) in srcset_candidates(value) {
+                    let url = normalize(url);
+                    if url.is_empty() {
+                        continue;
+                    }
+                    match keep(&url)? {
+                        Some(url) if descriptor.is_empty() => candidates.push(url),
+                        Some(url) => candidates.push(format!("{url} {descriptor}")),
+                        None => {}
+                    }
+                }
+
+                Ok((!candidates.is_empty()).then(|| candidates.join(", ")))
+            }
+            UrlList::Ping => {
+                let mut urls: Vec<String> = vec![];
+                for url in value.split_ascii_whitespace().map(normalize) {
+                    if let Some(url) = keep(&url)? {
+                        urls.push(url);
+                    }
+                }
+
+                Ok((!urls.is_empty()).then(|| urls.join(" ")))
+            }
+            // there's only one URL, so either it's kept, or the whole value goes
+            UrlList::Refresh => match refresh_url(value) {
+                Some(range) => Ok(keep(&normalize(&value[range.clone()]))?
+                    .map(|url| format!("{}{url}{}", &value[..range.start], &value[range.end..]))),
+                None => Ok(Some(value.to_string())),
+            },
+        }
+    }
+}
+
+// Splits `srcset` the way browsers do: a URL runs up to the next whitespace (so that commas in
+// `data:` URLs survive), and its descriptor up to the next comma.
+// See <https://html.spec.whatwg.org/multipage/images.html#parse-a-srcset-attribute>.
+fn srcset_candidates(value: &str) -> Vec<(&str, &str)> {
+    let mut candidates = vec![];
+    let mut rest = value;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        if rest.is_empty() {
+            return candidates;
+        }
+
+        let url_end = rest
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let (url, after) = rest.split_at(url_end);
+
+        if url.ends_with(',') {
+            candidates.push((url.trim_end_matches(','), ""));
+            rest = after;
+        } else {
+            let descriptor_end = after.find(',').unwrap_or(after.len());
+            candidates.push((url, after[..descriptor_end].trim()));
+            rest = &after[descriptor_end..];
+        }
+    }
+}
+
+// Where the URL in a refresh's `content`, like `5; url='/next'`, is, if there is one.
+// See <https://html.spec.whatwg.org/multipage/semantics.html#shared-declarative-refresh-steps>.
+fn refresh_url(content: &str) -> Option<Range<usize>> {
+    let rest = content
+        .trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+        .trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ';' || c == ',')
+        .trim_start();
+
+    let rest = match rest.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url") => {
+            match rest[3..].trim_start().strip_prefix('=') {
+                Some(url) => url.trim_start(),
+                // not `url=` after all, so this is where the URL starts
+                None => rest,
+            }
+        }
+        _ => rest,
+    };
+
+    let url = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => rest[1..].split(quote).next().unwrap_or_default(),
+        _ => rest.trim_end(),
+    };
+
+    // `url` is a slice of `content`
+    let start = url.as_ptr() as usize - content.as_ptr() as usize;
+    (!url.is_empty()).then_some(start..start + url.len())
+}
+
+/// An absolute URL, like `base_url:`, that relative URLs are resolved against.
+#[derive(Debug)]
+pub struct BaseUrl {
+    scheme: String,
+    // the scheme and authority, like `https://example.com`
+    origin: String,
+    // without its query or fragment, and at least `/`
+    path: String,
+}
+
+impl BaseUrl {
+    /// `url`, if it's absolute and hierarchical (like `https://example.com/docs/`).
+    pub fn parse(url: &str) -> Option<BaseUrl> {
+        let url = normalize(url);
+        let (scheme, after_scheme) = split_scheme(&url)?;
+        let after_scheme = after_scheme.strip_prefix("//")?;
+
+        let authority_end = after_scheme
+            .find(['/', '?', '#'])
+            .unwrap_or(after_scheme.len());
+        if authority_end == 0 {
+            return None;
+        }
+        let (authority, rest) = after_scheme.split_at(authority_end);
+        let path = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+
+        Some(BaseUrl {
+            origin: format!("{scheme}://{authority}"),
+            path: remove_dot_segments(path),
+            scheme,
+        })
+    }
+
+    /// `url` resolved against this base, unless it's already absolute, or only a fragment (like
+    /// `#top`), which keeps pointing within the document.
+    pub fn resolve<'u>(&self, url: &'u str) -> Cow<'u, str> {
+        if url.is_empty() || url.starts_with('#') || scheme(url).is_some() {
+            return Cow::Borrowed(url);
+        }
+
+        let Self {
+            scheme,
+            origin,
+            path,
+        } = self;
+        Cow::Owned(if url.starts_with("//") {
+            format!("{scheme}:{url}")
+        } else if url.starts_with('?') {
+            format!("{origin}{path}{url}")
+        } else {
+            let url = if url.starts_with('/') {
+                Cow::Borrowed(url)
+            } else {
+                let directory = &path[..=path.rfind('/').unwrap_or(0)];
+                Cow::Owned(format!("{directory}{url}"))
+            };
+            let path_end = url.find(['?', '#']).unwrap_or(url.len());
+            let (path, query_and_fragment) = url.split_at(path_end);
+            format!("{origin}{}{query_and_fragment}", remove_dot_segments(path))
+        })
+    }
+}
+
+/// `path` with its `.` and `..` segments resolved, the way RFC 3986 does when resolving a
+/// relative URL: `/docs/../faq/` is `/faq/`. It's always absolute, and at least `/`.
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = vec![];
+    let mut segments = path.strip_prefix('/').unwrap_or(path).split('/').peekable();
+
+    while let Some(segment) = segments.next() {
+        match segment {
+            "." => {}
+            ".." => {
+                output.pop();
+            }
+            segment => {
+                output.push(segment);
+                continue;
+            }
+        }
+        // `/docs/..` is the directory `/`, so it keeps its trailing slash
+        if segments.peek().is_none() {
+            output.push("");
+        }
+    }
+
+    format!("/{}", output.join("/"))
+}
+
+// Schemes whose URLs always have a host. Browsers skip however many `/` and `\` follow their
+// `:`, so `https:/evil.example` and `https:///evil.example` both lead to `evil.example`.
+const HOST_SCHEMES: &[&str] = &["http", "https", "ws", "wss", "ftp"];
+
+/// Whether a `normalize`d URL's scheme is one whose URLs always have a host, like `https`.
+pub fn has_host_scheme(url: &str) -> bool {
+    split_scheme(url).is_some_and(|(scheme, _)| HOST_SCHEMES.contains(&scheme.as_str()))
+}
+
+/// The host of a `normalize`d URL, lowercased, if it has one, like `example.com` in
+/// `https://user@example.com:8080/` or `//example.com/`.
+pub fn host(url: &str) -> Option<String> {
+    // browsers treat `\` like `/` here, so `https:\\evil.example` still has a host
+    let authority = match split_scheme(url) {
+        Some((scheme, after_scheme)) if HOST_SCHEMES.contains(&scheme.as_str()) => {
+            after_scheme.trim_start_matches(['/', '\\'])
+        }
+        after_scheme => after_scheme
+            .map_or(url, |(_, after_scheme)| after_scheme)
+            .strip_prefix(['/', '\\'])?
+            .strip_prefix(['/', '\\'])?,
+    };
+    let authority = &authority[..authority
+        .find(['/', '\\', '?', '#'])
+        .unwrap_or(authority.len())];
+    let host_and_port = authority.rsplit('@').next().unwrap_or_default();
+
+    let host = match host_and_port.strip_prefix('[') {
+        // an IPv6 address, which has colons of its own
+        Some(ipv6) => &host_and_port[..ipv6.find(']').map_or(host_and_port.len(), |i| i + 2)],
+        None => host_and_port.split(':').next().unwrap_or_default(),
+    };
+    let host = host.trim_end_matches('.');
+
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
@@ -0,0 +1,255 @@
+use std::{borrow::Cow, cell::RefCell, collections::HashMap, error::Error as StdError, fmt::Write};
+
+use magnus::{
+    gc, r_hash::ForEach, value::Opaque, value::ReprValue, Error, RHash, RString, Ruby, Value,
+};
+
+use crate::{
+    limits::RewriteAborted,
+    sanitizer_config::{config_name, invalid},
+    url::BaseUrl,
+};
+
+thread_local! {
+    // what a `url_rewriter:` callable raised, until `lol_html` has unwound and it can be
+    // re-raised as-is. Sanitizers are shared, so this can't live on one; a pass runs start to
+    // finish on the thread that started it, though.
+    static RAISED: RefCell<Option<Error>> = const { RefCell::new(None) };
+}
+
+/// How a URL gets rewritten: by filling in a template like `"https://camo.example/{hex(url)}"`,
+/// or by calling a Ruby callable with it.
+enum Rewriter {
+    Template(Vec<TemplatePart>),
+    Callable(Opaque<Value>),
+}
+
+enum TemplatePart {
+    Literal(String),
+    // `{url}`
+    Url,
+    // `{hex(url)}`, the URL's bytes in lowercase hex
+    Hex,
+    // `{escape(url)}`, the URL percent-encoded, for use in a query string
+    Escape,
+}
+
+/// `base_url:` and `url_rewriter:`, compiled. These apply to URLs which have already passed their
+/// protocol check; nothing they return is checked again.
+#[derive(Default)]
+pub struct UrlRewriting {
+    base_url: Option<BaseUrl>,
+    everywhere: Option<Rewriter>,
+    // element name => attribute name => rewriter, like `protocols`
+    by_attribute: HashMap<String, HashMap<String, Rewriter>>,
+}
+
+impl UrlRewriting {
+    pub fn from_config(config: RHash) -> Result<Self, Error> {
+        let ruby = Ruby::get().unwrap();
+        let mut url_rewriting = Self::default();
+
+        if let Some(base_url) = config.get(ruby.to_symbol("base_url")) {
+            if !base_url.is_nil() {
+                let url = RString::from_value(base_url)
+                    .map(|url| url.to_string())
+                    .transpose()?;
+                url_rewriting.base_url = Some(url.as_deref().and_then(BaseUrl::parse).ok_or_else(
+                    || {
+                        invalid(format!(
+                            "`base_url` must be an absolute URL, like \"https://example.com/\", not `{}`",
+                            base_url.inspect()
+                        ))
+                    },
+                )?);
+            }
+        }
+
+        let Some(url_rewriter) = config.get(ruby.to_symbol("url_rewriter")) else {
+            return Ok(url_rewriting);
+        };
+        if url_rewriter.is_nil() {
+            return Ok(url_rewriting);
+        }
+        let Some(by_element) = RHash::from_value(url_rewriter) else {
+            url_rewriting.everywhere = Some(Rewriter::new("url_rewriter", url_rewriter)?);
+            return Ok(url_rewriting);
+        };
+
+        by_element.foreach(|element: Value, by_attribute: Value| {
+            let path = format!("url_rewriter[{}]", element.inspect());
+            let Some(element) = config_name(element) else {
+                return Err(invalid(format!(
+                    "`url_rewriter` keys must be element names; found `{}`",
+                    element.inspect()
+                )));
+            };
+            let Some(by_attribute) = RHash::from_value(by_attribute) else {
+                return Err(invalid(format!(
+                    "`{path}` must be a Hash of attribute names and rewriters, not `{}`",
+                    by_attribute.inspect()
+                )));
+            };
+
+            let rewriters = url_rewriting.by_attribute.entry(element).or_default();
+            by_attribute.foreach(|attribute: Value, rewriter: Value| {
+                let path = format!("{path}[{}]", attribute.inspect());
+                let Some(attribute) = config_name(attribute) else {
+                    return Err(invalid(format!(
+                        "`{path}` must be keyed by an attribute name"
+                    )));
+                };
+
+                rewriters.insert(attribute, Rewriter::new(&path, rewriter)?);
+                Ok(ForEach::Continue)
+            })?;
+
+            Ok(ForEach::Continue)
+        })?;
+
+        Ok(url_rewriting)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.base_url.is_none() && self.everywhere.is_none() && self.by_attribute.is_empty()
+    }
+
+    /// `url`, from the `attribute` of an `element`, resolved against `base_url:` and then passed to
+    /// its `url_rewriter:`; `None` if a callable returned `nil`, and the attribute should be dropped.
+    pub fn rewrite<'u>(
+        &self,
+        element: &str,
+        attribute: &str,
+        url: &'u str,
+    ) -> Result<Option<Cow<'u, str>>, Error> {
+        let url = match &self.base_url {
+            Some(base_url) => base_url.resolve(url),
+            None => Cow::Borrowed(url),
+        };
+
+        // fragments only ever point within the document, so there's nothing to rewrite
+        if url.is_empty() || url.starts_with('#') {
+            return Ok(Some(url));
+        }
+
+        let rewriter = self
+            .by_attribute
+            .get(element)
+            .and_then(|rewriters| rewriters.get(attribute))
+            .or(self.everywhere.as_ref());
+        match rewriter {
+            Some(rewriter) => Ok(rewriter.rewrite(&url)?.map(Cow::Owned)),
+            None => Ok(Some(url)),
+        }
+    }
+
+    /// Holds on to an exception a callable raised, so that it can be re-raised once `lol_html`
+    /// has unwound, rather than being flattened into a message.
+    pub fn raised(err: Error) -> Box<dyn StdError + Send + Sync> {
+        RAISED.set(Some(err));
+        Box::new(RewriteAborted::UrlRewriterRaised)
+    }
+
+    pub fn take_raised() -> Option<Error> {
+        RAISED.take()
+    }
+
+    /// Callables are also held by the sanitizer's config, but that doesn't keep them in place.
+    /// Everything holding these rules calls this: the `Selma::Sanitizer`, and every rewriter
+    /// with a copy of it.
+    pub fn mark(&self, marker: &gc::Marker) {
+        let rewriters = self.by_attribute.values().flat_map(HashMap::values);
+        for rewriter in self.everywhere.iter().chain(rewriters) {
+            if let Rewriter::Callable(callable) = rewriter {
+                marker.mark(*callable);
+            }
+        }
+    }
+}
+
+impl Rewriter {
+    fn new(path: &str, value: Value) -> Result<Self, Error> {
+        if let Some(template) = RString::from_value(value) {
+            return Self::parse_template(path, &template.to_string()?);
+        }
+
+        if !value.respond_to("call", false)? {
+            return Err(invalid(format!(
+                "`{path}` must be a template String, or respond to `call`; found `{}`",
+                value.inspect()
+            )));
+        }
+
+        // a callable that isn't shareable is kept as it is; the sanitizer holding it just isn't
+        // shareable between Ractors either
+        Ok(Rewriter::Callable(value.into()))
+    }
+
+    fn parse_template(path: &str, template: &str) -> Result<Self, Error> {
+        let mut parts = vec![];
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Literal(rest[..start].to_string()));
+            }
+            let Some(length) = rest[start..].find('}') else {
+                return Err(invalid(format!(
+                    "`{path}` has an unclosed `{{` in {template:?}"
+                )));
+            };
+            parts.push(match &rest[start + 1..start + length] {
+                "url" => TemplatePart::Url,
+                "hex(url)" => TemplatePart::Hex,
+                "escape(url)" => TemplatePart::Escape,
+                placeholder => {
+                    return Err(invalid(format!(
+                        "`{path}` has an unknown placeholder `{{{placeholder}}}`; use `{{url}}`, `{{hex(url)}}` or `{{escape(url)}}`"
+                    )))
+                }
+            });
+            rest = &rest[start + length + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_string()));
+        }
+
+        Ok(Rewriter::Template(parts))
+    }
+
+    fn rewrite(&self, url: &str) -> Result<Option<String>, Error> {
+        let parts = match self {
+            Rewriter::Template(parts) => parts,
+            Rewriter::Callable(callable) => {
+                let ruby = Ruby::get().unwrap();
+                let rewritten: Option<RString> =
+                    ruby.get_inner(*callable).funcall("call", (url,))?;
+                return rewritten.map(|url| url.to_string()).transpose();
+            }
+        };
+
+        let mut rewritten = String::new();
+        for part in parts {
+            match part {
+                TemplatePart::Literal(literal) => rewritten.push_str(literal),
+                TemplatePart::Url => rewritten.push_str(url),
+                TemplatePart::Hex => {
+                    for byte in url.bytes() {
+                        write!(rewritten, "{byte:02x}").unwrap();
+                    }
+                }
+                TemplatePart::Escape => {
+                    for byte in url.bytes() {
+                        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                            rewritten.push(byte as char);
+                        } else {
+                            write!(rewritten, "%{byte:02X}").unwrap();
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Some(rewritten))
+    }
+}
@@ -11,3 +11,27 @@ require_relative "selma/sanitizer"
 require_relative "selma/html"
 require_relative "selma/rewriter"
 require_relative "selma/selector"
+
+module Selma
+  class << self
+    # A callable which receives `(event, payload)` for every `Selma::Rewriter#rewrite`.
+    attr_reader :instrument
+
+    def instrument=(callback)
+      raise ArgumentError, "instrument must respond to `call`" unless callback.nil? || callback.respond_to?(:call)
+
+      @instrument = callback
+    end
+
+    # Sanitizes an HTML fragment, like a comment or a post body, against `config`.
+    #
+    # Every call compiles `config` into a new `Selma::Sanitizer`, which costs about as much
+    # as the sanitizing itself for a short fragment. Nothing is cached, so a config that's
+    # changed between calls is always honored, and no state is shared between threads or
+    # Ractors. To sanitize many fragments with one config, build a `Selma::Sanitizer` once
+    # and call its `#sanitize` instead.
+    def sanitize(html, config = Sanitizer::Config::DEFAULT)
+      Sanitizer.new(config).sanitize(html)
+    end
+  end
+end
@@ -7,6 +7,13 @@ module Selma
         max_allowed_memory_usage: nil,
         preallocated_parsing_buffer_size: nil,
       },
+      limits: {
+        max_output_bytes: nil,
+        max_element_depth: nil,
+        max_elements: nil,
+        on_limit: :raise,
+      },
+      timeout: nil,
     }
   end
 end
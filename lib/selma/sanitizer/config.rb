@@ -54,7 +54,7 @@ module Selma
         # Returns `true` if `dup` may be safely called on _value_, `false`
         # otherwise.
         def can_dupe?(value)
-          !(value == true || value == false || value.nil? || value.is_a?(Method) || value.is_a?(Numeric) || value.is_a?(Symbol))
+          !(value == true || value == false || value.nil? || value.is_a?(Method) || value.is_a?(Proc) || value.is_a?(Module) || value.is_a?(Numeric) || value.is_a?(Symbol))
         end
       end
     end
@@ -64,4 +64,5 @@ end
 require "selma/sanitizer/config/basic"
 require "selma/sanitizer/config/default"
 require "selma/sanitizer/config/relaxed"
+require "selma/sanitizer/config/foreign_content"
 require "selma/sanitizer/config/restricted"
@@ -76,6 +76,18 @@ module Selma
           "section",
           "ul",
         ],
+
+        # What to do with custom elements (like `<my-widget>`) which aren't
+        # listed in `elements`: `:remove` them like any other element that isn't
+        # allowed, `:allow` all of them, or allow only the names in an Array.
+        custom_elements: :remove,
+
+        # Rules for inline SVG and MathML, keyed by `:svg` or `:math`, each a Hash
+        # with its own `elements`, `attributes` and `protocols`. Elements parsed
+        # into a namespace listed here are judged by its rules alone; a namespace
+        # that isn't listed falls back to the HTML rules above, which wipe `<svg>`
+        # and `<math>` along with their contents.
+        foreign_content: {},
       )
     end
   end
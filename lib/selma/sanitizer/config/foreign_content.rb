@@ -0,0 +1,201 @@
+# frozen_string_literal: true
+
+module Selma
+  class Sanitizer
+    module Config
+      FOREIGN_CONTENT = freeze_config(
+        merge(
+          RELAXED,
+          foreign_content: {
+            svg: {
+              # no `<script>`, `<style>`, `<foreignObject>`, `<image>`, or the
+              # animation elements, which can rewrite other attributes
+              elements: [
+                "a",
+                "circle",
+                "clipPath",
+                "defs",
+                "desc",
+                "ellipse",
+                "g",
+                "line",
+                "linearGradient",
+                "marker",
+                "mask",
+                "path",
+                "pattern",
+                "polygon",
+                "polyline",
+                "radialGradient",
+                "rect",
+                "stop",
+                "svg",
+                "symbol",
+                "text",
+                "textPath",
+                "title",
+                "tspan",
+                "use",
+              ],
+
+              attributes: {
+                all: [
+                  "aria-hidden",
+                  "aria-label",
+                  "class",
+                  "clip-path",
+                  "clip-rule",
+                  "cx",
+                  "cy",
+                  "d",
+                  "display",
+                  "dominant-baseline",
+                  "dx",
+                  "dy",
+                  "fill",
+                  "fill-opacity",
+                  "fill-rule",
+                  "focusable",
+                  "font-family",
+                  "font-size",
+                  "font-style",
+                  "font-weight",
+                  "height",
+                  "id",
+                  "marker-end",
+                  "marker-mid",
+                  "marker-start",
+                  "mask",
+                  "opacity",
+                  "points",
+                  "preserveAspectRatio",
+                  "r",
+                  "role",
+                  "rx",
+                  "ry",
+                  "stroke",
+                  "stroke-dasharray",
+                  "stroke-dashoffset",
+                  "stroke-linecap",
+                  "stroke-linejoin",
+                  "stroke-miterlimit",
+                  "stroke-opacity",
+                  "stroke-width",
+                  "text-anchor",
+                  "transform",
+                  "viewBox",
+                  "visibility",
+                  "width",
+                  "x",
+                  "x1",
+                  "x2",
+                  "xmlns",
+                  "xmlns:xlink",
+                  "y",
+                  "y1",
+                  "y2",
+                ],
+                "a" => ["href", "xlink:href"],
+                "clipPath" => ["clipPathUnits"],
+                "linearGradient" => ["gradientTransform", "gradientUnits", "href", "spreadMethod", "xlink:href"],
+                "marker" => ["markerHeight", "markerUnits", "markerWidth", "orient", "refX", "refY"],
+                "mask" => ["maskContentUnits", "maskUnits"],
+                "pattern" => ["href", "patternContentUnits", "patternTransform", "patternUnits", "xlink:href"],
+                "radialGradient" => ["fr", "fx", "fy", "gradientTransform", "gradientUnits", "href", "spreadMethod", "xlink:href"],
+                "stop" => ["offset", "stop-color", "stop-opacity"],
+                "textPath" => ["href", "startOffset", "xlink:href"],
+                "use" => ["href", "xlink:href"],
+              },
+
+              # `href` and `xlink:href` not listed here may only point within
+              # the document, like `href="#icon"`
+              protocols: {
+                "a" => {
+                  "href" => VALID_PROTOCOLS,
+                  "xlink:href" => VALID_PROTOCOLS,
+                },
+              },
+            },
+
+            math: {
+              # no `<annotation-xml>`, which can hold HTML
+              elements: [
+                "annotation",
+                "math",
+                "menclose",
+                "merror",
+                "mfrac",
+                "mi",
+                "mmultiscripts",
+                "mn",
+                "mo",
+                "mover",
+                "mpadded",
+                "mphantom",
+                "mprescripts",
+                "mroot",
+                "mrow",
+                "ms",
+                "mspace",
+                "msqrt",
+                "mstyle",
+                "msub",
+                "msubsup",
+                "msup",
+                "mtable",
+                "mtd",
+                "mtext",
+                "mtr",
+                "munder",
+                "munderover",
+                "none",
+                "semantics",
+              ],
+
+              attributes: {
+                all: [
+                  "class",
+                  "dir",
+                  "display",
+                  "displaystyle",
+                  "id",
+                  "mathbackground",
+                  "mathcolor",
+                  "mathsize",
+                  "mathvariant",
+                  "scriptlevel",
+                  "xmlns",
+                ],
+                "annotation" => ["encoding"],
+                "menclose" => ["notation"],
+                "mfrac" => ["linethickness"],
+                "mo" => [
+                  "accent",
+                  "fence",
+                  "form",
+                  "largeop",
+                  "lspace",
+                  "maxsize",
+                  "minsize",
+                  "movablelimits",
+                  "rspace",
+                  "separator",
+                  "stretchy",
+                  "symmetric",
+                ],
+                "mover" => ["accent"],
+                "mpadded" => ["depth", "height", "lspace", "voffset", "width"],
+                "mspace" => ["depth", "height", "width"],
+                "mtable" => ["columnalign", "columnlines", "columnspacing", "rowalign", "rowlines", "rowspacing"],
+                "mtd" => ["columnalign", "columnspan", "rowalign", "rowspan"],
+                "mtr" => ["columnalign", "rowalign"],
+                "munder" => ["accentunder"],
+                "munderover" => ["accent", "accentunder"],
+              },
+            },
+          },
+        ),
+      )
+    end
+  end
+end
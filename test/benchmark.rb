@@ -102,3 +102,6 @@ compare_sanitize
 
 puts "Compare rewriting"
 compare_rewriting
+
+puts "Compare handler scaling"
+load "#{DIR}/benchmark/handler_scaling.rb"
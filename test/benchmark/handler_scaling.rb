@@ -0,0 +1,34 @@
+# frozen_string_literal: true
+
+# Rewrites the benchmark documents with 1, 5 and 20 copies of the same handler, each tracking
+# ancestors and ignoring text within some of them, to show how the cost of a rewrite grows with
+# the number of handlers.
+
+require "benchmark/ips"
+require "selma"
+require_relative "selma_config"
+
+HANDLER_SCALING_DIR = File.expand_path(File.dirname(__FILE__))
+
+HANDLER_SCALING_DOCUMENTS = ["sm", "md", "lg"].map do |label|
+  html = File.read("#{HANDLER_SCALING_DIR}/html/document-#{label}.html")
+  [html.encode("UTF-8", invalid: :replace, undef: :replace), label]
+end
+
+HANDLER_SCALING_DOCUMENTS.each do |(html, label)|
+  puts("input size = #{html.bytesize} bytes, #{(html.bytesize.to_f / 1_000_000).round(2)} MB\n\n")
+
+  Benchmark.ips do |x|
+    x.config(time: 30, warmup: 10)
+
+    [1, 5, 20].each do |count|
+      handlers = Array.new(count) { SelmaConfig::AncestorHandler.new }
+
+      x.report("selma-#{count}-handlers-#{label}") do
+        Selma::Rewriter.new(sanitizer: nil, handlers: handlers).rewrite(html)
+      end
+    end
+
+    x.compare!
+  end
+end
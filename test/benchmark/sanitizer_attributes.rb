@@ -0,0 +1,37 @@
+# frozen_string_literal: true
+
+# Sanitizes documents where nearly every element carries a handful of attributes, some allowed
+# and some not, so that the time goes into attribute lookups rather than parsing.
+
+require "benchmark/ips"
+require "selma"
+
+ATTRIBUTE_HEAVY = Array.new(2_000) do |i|
+  <<~HTML
+    <div id="d#{i}" class="row" title="Row #{i}" data-index="#{i}" onclick="steal()" style="color: red">
+      <a href="https://example.com/#{i}" rel="nofollow" name="a#{i}" hreflang="en" onmouseover="steal()">link</a>
+      <img src="https://example.com/#{i}.png" alt="Image #{i}" width="16" height="16" loading="lazy">
+      <span lang="en" dir="ltr" aria-label="label" data-foo="bar">text &amp; more</span>
+    </div>
+  HTML
+end.join
+
+SANITIZERS = {
+  "default" => Selma::Sanitizer.new(Selma::Sanitizer::Config::DEFAULT),
+  "relaxed" => Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED),
+  "relaxed+data" => Selma::Sanitizer.new(
+    Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, attributes: { all: [:data] }),
+  ),
+}
+
+puts("input size = #{ATTRIBUTE_HEAVY.bytesize} bytes, #{ATTRIBUTE_HEAVY.scan("=").size} attributes\n\n")
+
+Benchmark.ips do |x|
+  x.config(time: 20, warmup: 5)
+
+  SANITIZERS.each do |label, sanitizer|
+    x.report("attributes-#{label}") { sanitizer.sanitize(ATTRIBUTE_HEAVY) }
+  end
+
+  x.compare!
+end
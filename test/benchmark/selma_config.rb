@@ -36,4 +36,19 @@ module SelmaConfig
       element.remove
     end
   end
+
+  # every handler needs the ancestor stack, but rarely matches anything
+  class AncestorHandler
+    SELECTOR = Selma::Selector.new(match_element: "blink", match_text_within: "*", ignore_text_within: "pre, code, a")
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      element.inside?(".markdown-body")
+    end
+
+    def handle_text_chunk(text_chunk); end
+  end
 end
@@ -0,0 +1,100 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class SelmaInstrumentTest < Minitest::Test
+  class Explode
+    SELECTOR = Selma::Selector.new(match_element: "p")
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      raise "boom"
+    end
+  end
+
+  def setup
+    @events = []
+    Selma.instrument = ->(event, payload) { @events << [event, payload] }
+  end
+
+  def teardown
+    Selma.instrument = nil
+  end
+
+  def test_instrument_must_be_callable
+    assert_raises(ArgumentError) do
+      Selma.instrument = "not callable"
+    end
+  end
+
+  def test_rewrite_is_instrumented
+    html = "<div><p>hello</p><script>alert(1)</script><iframe></iframe></div>"
+    sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)
+    result = Selma::Rewriter.new(sanitizer: sanitizer).rewrite(html)
+
+    assert_equal(1, @events.size)
+
+    event, payload = @events.first
+
+    assert_equal("rewrite.selma", event)
+    assert_equal(html.bytesize, payload[:input_bytes])
+    assert_equal(result.bytesize, payload[:output_bytes])
+    assert_equal(2, payload[:elements_removed])
+    assert_operator(payload[:duration], :>, 0.0)
+    refute(payload.key?(:exception))
+  end
+
+  class AppendScript
+    SELECTOR = Selma::Selector.new(match_element: "p")
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      element.append("<script>alert(1)</script>", as: :html)
+    end
+  end
+
+  def test_elements_removed_counts_the_final_sanitization_pass
+    sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)
+    result = Selma::Rewriter.new(sanitizer: sanitizer, handlers: [AppendScript.new]).rewrite("<p>hello</p><script>x</script>")
+
+    assert_equal("<p>hello</p>", result)
+    assert_equal(2, @events.first.last[:elements_removed])
+  end
+
+  def test_nothing_is_removed_without_a_sanitizer
+    Selma::Rewriter.new(sanitizer: nil).rewrite("<script>alert(1)</script>")
+
+    assert_equal(0, @events.first.last[:elements_removed])
+  end
+
+  def test_failed_rewrites_are_instrumented
+    assert_raises(RuntimeError) do
+      Selma::Rewriter.new(handlers: [Explode.new]).rewrite("<p>hello</p>")
+    end
+
+    _, payload = @events.first
+
+    assert_nil(payload[:output_bytes])
+    assert_kind_of(RuntimeError, payload[:exception])
+  end
+
+  def test_callback_errors_propagate
+    Selma.instrument = ->(_event, _payload) { raise ArgumentError, "apm is down" }
+
+    assert_raises(ArgumentError) do
+      Selma::Rewriter.new.rewrite("<p>hello</p>")
+    end
+  end
+
+  def test_no_callback
+    Selma.instrument = nil
+
+    assert_equal("<p>hello</p>", Selma::Rewriter.new.rewrite("<p>hello</p>"))
+  end
+end
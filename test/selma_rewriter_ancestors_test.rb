@@ -0,0 +1,111 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class SelmaRewriterAncestorsTest < Minitest::Test
+  class RecordAncestors
+    SELECTOR = Selma::Selector.new(match_element: "em")
+
+    attr_reader :seen
+
+    def initialize
+      @seen = []
+    end
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      @seen << element.ancestors.map(&:tag_name)
+    end
+  end
+
+  class RecordImpliedAncestors < RecordAncestors
+    SELECTOR = Selma::Selector.new(match_element: "li, p, td")
+
+    def selector
+      SELECTOR
+    end
+  end
+
+  def ancestors_of_em(html)
+    handler = RecordAncestors.new
+    Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite(html)
+    handler.seen
+  end
+
+  def test_unclosed_paragraphs_are_implicitly_closed
+    assert_equal([["div", "p"], ["div", "p"]], ancestors_of_em("<div><p><em>a</em><p><em>b</em></div>"))
+  end
+
+  def test_block_elements_close_an_open_paragraph
+    assert_equal([["p"], ["div"], ["ul", "li"]], ancestors_of_em("<p><em>a</em><div><em>b</em></div><ul><li><em>c</em></ul>"))
+  end
+
+  def test_implicitly_closed_elements_are_not_ancestors_of_what_closes_them
+    handler = RecordImpliedAncestors.new
+    html = "<ul><li>a<li>b</ul><div><p>c<p>d</div><table><tr><td>e<td>f</table>"
+    Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite(html)
+
+    assert_equal([["ul"], ["ul"], ["div"], ["div"], ["table", "tr"], ["table", "tr"]], handler.seen)
+  end
+
+  def test_paragraphs_are_not_closed_across_a_button
+    assert_equal([["p", "button", "div"]], ancestors_of_em("<p><button><div><em>a</em></div></button></p>"))
+  end
+
+  def test_list_items_are_implicitly_closed
+    html = "<ul><li><em>a</em><li><span><em>b</em></span><li><ol><li><em>c</em></ol></ul>"
+
+    assert_equal([["ul", "li"], ["ul", "li", "span"], ["ul", "li", "ol", "li"]], ancestors_of_em(html))
+  end
+
+  def test_definition_list_items_are_implicitly_closed
+    assert_equal([["dl", "dt"], ["dl", "dd"]], ancestors_of_em("<dl><dt><em>a</em><dd><em>b</em></dl>"))
+  end
+
+  def test_table_cells_and_rows_are_implicitly_closed
+    html = "<table><tr><td><em>a</em><td><em>b</em><tr><th><em>c</em></table>"
+
+    assert_equal([["table", "tr", "td"], ["table", "tr", "td"], ["table", "tr", "th"]], ancestors_of_em(html))
+  end
+
+  def test_void_elements_are_not_ancestors
+    assert_equal([["p"]], ancestors_of_em("<p><br><img src=x><input><em>a</em></p>"))
+  end
+
+  def test_foreign_self_closing_elements_are_not_ancestors
+    html = "<svg><path d='M0'/><g><circle r='1'/></g></svg><p><em>a</em></p><math><mi/></math><div><em>b</em></div>"
+
+    assert_equal([["p"], ["div"]], ancestors_of_em(html))
+  end
+
+  def test_self_closing_syntax_is_ignored_for_html_elements
+    # like a browser, `<my-widget/>` is treated as an open tag in HTML
+    assert_equal([["div", "my-widget"], []], ancestors_of_em("<div><my-widget/><em>a</em></div><em>b</em>"))
+  end
+
+  def test_end_tags_close_elements_left_open_inside_them
+    assert_equal([["div"]], ancestors_of_em("<section><span><b><i>x</section><div><em>a</em></div>"))
+  end
+
+  class IgnoreParagraphText
+    SELECTOR = Selma::Selector.new(match_text_within: "*", ignore_text_within: ["p", "path"])
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_text_chunk(text)
+      text.replace(text.to_s.upcase, as: :text)
+    end
+  end
+
+  def test_ignore_text_within_recovers_after_implied_end_tags
+    html = "<div><p>one<p>two</div><span>three</span><svg><path/></svg><b>four</b>"
+    result = Selma::Rewriter.new(sanitizer: nil, handlers: [IgnoreParagraphText.new]).rewrite(html)
+
+    assert_equal("<div><p>one<p>two</div><span>THREE</span><svg><path/></svg><b>FOUR</b>", result)
+  end
+end
@@ -0,0 +1,158 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class SelmaRewriterLimitsTest < Minitest::Test
+  class CountElements
+    SELECTOR = Selma::Selector.new(match_element: "*")
+
+    attr_reader :tag_names
+
+    def initialize
+      @tag_names = []
+    end
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      @tag_names << element.tag_name
+    end
+  end
+
+  def test_limits_must_be_positive_integers
+    assert_raises(ArgumentError) do
+      Selma::Rewriter.new(options: { limits: { max_elements: 0 } })
+    end
+
+    assert_raises(ArgumentError) do
+      Selma::Rewriter.new(options: { limits: { max_element_depth: "10" } })
+    end
+
+    assert_raises(ArgumentError) do
+      Selma::Rewriter.new(options: { limits: { max_output_bytes: 10, on_limit: :explode } })
+    end
+  end
+
+  def test_limits_under_the_threshold_do_nothing
+    html = "<div><p>Hello <strong>world</strong></p></div>"
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [CountElements.new], options: { limits: { max_output_bytes: 1000, max_element_depth: 3, max_elements: 4 } })
+
+    assert_equal(html, rewriter.rewrite(html))
+  end
+
+  def test_max_element_depth_raises
+    html = nest_html_content("deep", 20)
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [CountElements.new], options: { limits: { max_element_depth: 10 } })
+
+    error = assert_raises(Selma::Rewriter::LimitExceededError) do
+      rewriter.rewrite(html)
+    end
+
+    assert_match(/max_element_depth \(10\)/, error.message)
+  end
+
+  def test_max_element_depth_follows_implied_end_tags
+    html = "<ul>#{"<li><p>item" * 300}</ul><div>#{"<p>para" * 300}</div><dl>#{"<dt>term<dd>def" * 300}</dl>"
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [CountElements.new], options: { limits: { max_element_depth: 256 } })
+
+    assert_equal(html, rewriter.rewrite(html))
+    assert_equal(html, Selma::Rewriter.new(sanitizer: nil, options: { limits: { max_element_depth: 3 } }).rewrite(html))
+  end
+
+  def test_limit_exceeded_error_is_a_runtime_error
+    assert_operator(Selma::Rewriter::LimitExceededError, :<, RuntimeError)
+  end
+
+  def test_max_element_depth_truncates_the_deep_subtree
+    html = "<div><span><span><span>too deep</span></span></span><p>fine</p></div>"
+    handler = CountElements.new
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [handler], options: { limits: { max_element_depth: 3, on_limit: :truncate } })
+
+    assert_equal("<div><span><span></span></span><p>fine</p></div>", rewriter.rewrite(html))
+    assert_equal(["div", "span", "span", "p"], handler.tag_names)
+  end
+
+  def test_max_elements_raises
+    html = "<p>a</p>" * 10
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [CountElements.new], options: { limits: { max_elements: 5 } })
+
+    assert_raises(Selma::Rewriter::LimitExceededError) do
+      rewriter.rewrite(html)
+    end
+  end
+
+  def test_max_elements_truncates_and_closes_open_tags
+    html = "<div><p>a</p><p>b</p><p>c</p>tail</div><p>after</p>"
+    handler = CountElements.new
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [handler], options: { limits: { max_elements: 3, on_limit: :truncate } })
+
+    assert_equal("<div><p>a</p><p>b</p></div>", rewriter.rewrite(html))
+    assert_equal(["div", "p", "p"], handler.tag_names)
+  end
+
+  def test_max_output_bytes_raises
+    html = "<p>#{"a" * 100}</p>" * 100
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [CountElements.new], options: { limits: { max_output_bytes: 1_000 } })
+
+    assert_raises(Selma::Rewriter::LimitExceededError) do
+      rewriter.rewrite(html)
+    end
+  end
+
+  def test_max_output_bytes_raises_for_output_written_after_the_last_handler
+    html = "<p>#{"a" * 2_000}</p>"
+    rewriter = Selma::Rewriter.new(sanitizer: nil, options: { limits: { max_output_bytes: 1_000 } })
+
+    assert_raises(Selma::Rewriter::LimitExceededError) do
+      rewriter.rewrite(html)
+    end
+  end
+
+  def test_max_output_bytes_applies_to_the_final_sanitization_passes
+    html = "<h2>Title</h2>" * 10
+    sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { ids: true }))
+    rewriter = Selma::Rewriter.new(sanitizer: sanitizer, options: { limits: { max_output_bytes: 200 } })
+
+    assert_raises(Selma::Rewriter::LimitExceededError) do
+      rewriter.rewrite(html)
+    end
+  end
+
+  def test_max_output_bytes_truncates
+    html = "<div>#{"<p>#{"a" * 100}</p>" * 100}</div>"
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [CountElements.new], options: { limits: { max_output_bytes: 1_000, on_limit: :truncate } })
+
+    result = rewriter.rewrite(html)
+
+    assert_operator(result.bytesize, :<, 1_200)
+    assert(result.end_with?("</p></div>"))
+  end
+
+  def test_max_output_bytes_truncates_after_the_content_that_crosses_it
+    html = "<p>a</p>" * 100
+
+    {
+      16 => "<p>a</p><p>a</p>",
+      20 => "<p>a</p><p>a</p><p>a</p>",
+      10 => "<p>a</p><p></p>",
+    }.each do |max, expected|
+      rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [CountElements.new], options: { limits: { max_output_bytes: max, on_limit: :truncate } })
+      result = rewriter.rewrite(html)
+
+      assert_equal(expected, result, max)
+      assert_equal(expected.bytesize, result.bytesize, max)
+    end
+  end
+
+  def test_limits_apply_to_sanitization
+    html = nest_html_content("deep", 20)
+    sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)
+    rewriter = Selma::Rewriter.new(sanitizer: sanitizer, options: { limits: { max_element_depth: 10 } })
+
+    assert_raises(Selma::Rewriter::LimitExceededError) do
+      rewriter.rewrite(html)
+    end
+  end
+end
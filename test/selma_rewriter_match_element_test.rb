@@ -126,6 +126,113 @@ class SelmaRewriterMatchElementTest < Minitest::Test
     Selma::Rewriter.new(sanitizer: nil, handlers: [GetEmptyAncestors.new]).rewrite(frag)
   end
 
+  class RichAncestors
+    SELECTOR = Selma::Selector.new(match_element: "strong")
+
+    attr_reader :ancestors, :inside, :closest
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      @ancestors = element.ancestors
+      @inside = [element.inside?("div.markdown-body"), element.inside?("article"), element.inside?(".markdown-body > section#intro")]
+      @closest = [element.closest("[id]"), element.closest("div"), element.closest("table")]
+    end
+  end
+
+  def test_that_ancestors_are_snapshots
+    frag = %(<div class="markdown-body wide" data-theme="dark"><section id="intro"><p><strong>Wow!</strong></p></section></div>)
+    handler = RichAncestors.new
+    Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite(frag)
+
+    div, section, p = handler.ancestors
+
+    assert_equal(["div", "section", "p"], handler.ancestors.map(&:tag_name))
+    assert_equal([0, 1, 2], handler.ancestors.map(&:depth))
+    assert_equal(["markdown-body", "wide"], div.classes)
+    assert(div.has_class?("wide"))
+    refute(div.has_class?("narrow"))
+    assert_equal({ "class" => "markdown-body wide", "data-theme" => "dark" }, div.attributes)
+    assert_equal("dark", div["data-theme"])
+    assert_nil(div.id)
+    assert_equal("intro", section.id)
+    assert_empty(p.attributes)
+    assert_equal("p", p.to_s)
+    assert_includes(handler.ancestors, "section")
+  end
+
+  def test_that_it_checks_ancestors_with_css
+    frag = %(<div class="markdown-body wide"><section id="intro"><p><strong>Wow!</strong></p></section></div>)
+    handler = RichAncestors.new
+    Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite(frag)
+
+    assert_equal([true, false, true], handler.inside)
+
+    section, div, table = handler.closest
+
+    assert_equal("section", section.tag_name)
+    assert_equal(1, section.depth)
+    assert_equal("div", div.tag_name)
+    assert_nil(table)
+  end
+
+  class RecordAncestors
+    SELECTOR = Selma::Selector.new(match_element: "*")
+
+    attr_reader :seen
+
+    def initialize
+      @seen = []
+    end
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      @seen << [element.tag_name, element.ancestors.map(&:tag_name)]
+    end
+  end
+
+  def test_that_all_handlers_share_the_same_ancestors
+    frag = %(<div><my-widget><p>Hi <br> <em>there</em></p></my-widget></div><span>bye</span>)
+    handlers = [RecordAncestors.new, RecordAncestors.new, RecordAncestors.new]
+    Selma::Rewriter.new(sanitizer: nil, handlers: handlers).rewrite(frag)
+
+    expected = [
+      ["div", []],
+      ["my-widget", ["div"]],
+      ["p", ["div", "my-widget"]],
+      ["br", ["div", "my-widget", "p"]],
+      ["em", ["div", "my-widget", "p"]],
+      ["span", []],
+    ]
+
+    handlers.each do |handler|
+      assert_equal(expected, handler.seen)
+    end
+  end
+
+  class InvalidInside
+    SELECTOR = Selma::Selector.new(match_element: "strong")
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      element.inside?("p:first-child")
+    end
+  end
+
+  def test_that_inside_raises_on_unsupported_css
+    assert_raises(RuntimeError) do
+      Selma::Rewriter.new(sanitizer: nil, handlers: [InvalidInside.new]).rewrite("<p><strong>Wow!</strong></p>")
+    end
+  end
+
   class AppendHtml
     SELECTOR = Selma::Selector.new(match_element: "strong")
 
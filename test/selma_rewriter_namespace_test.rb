@@ -0,0 +1,135 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class SelmaRewriterNamespaceTest < Minitest::Test
+  class RecordElements
+    attr_reader :selector, :seen
+
+    def initialize(css)
+      @selector = Selma::Selector.new(match_element: css)
+      @seen = []
+    end
+
+    def handle_element(element)
+      @seen << [element.tag_name, element.namespace_uri, element.svg?, element.mathml?]
+    end
+  end
+
+  class RecordAncestors
+    SELECTOR = Selma::Selector.new(match_element: "em, path")
+
+    attr_reader :seen
+
+    def initialize
+      @seen = []
+    end
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      @seen << element.ancestors.map { |a| [a.tag_name, a.svg?, a.mathml?] }
+    end
+  end
+
+  class WrapText
+    def initialize(ignore_text_within)
+      @selector = Selma::Selector.new(match_text_within: "*", ignore_text_within: ignore_text_within)
+    end
+
+    attr_reader :selector
+
+    def handle_text_chunk(text)
+      text.replace(text.to_s.upcase, as: :text)
+    end
+  end
+
+  HTML = "<title>Doc</title><a>html</a><svg><title>Icon</title><a>svg</a></svg><math><mi>x</mi></math>"
+
+  def seen_by(css, html = HTML)
+    handler = RecordElements.new(css)
+    Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite(html)
+    handler.seen
+  end
+
+  def test_element_namespace
+    assert_equal(
+      [
+        ["a", "http://www.w3.org/1999/xhtml", false, false],
+        ["a", "http://www.w3.org/2000/svg", true, false],
+        ["mi", "http://www.w3.org/1998/Math/MathML", false, true],
+      ],
+      seen_by("a, mi"),
+    )
+  end
+
+  def test_html_inside_foreign_object_is_in_the_html_namespace
+    assert_equal(
+      [["p", "http://www.w3.org/1999/xhtml", false, false]],
+      seen_by("p", "<svg><foreignObject><p>hi</p></foreignObject></svg>"),
+    )
+  end
+
+  def test_match_element_with_a_namespace_prefix
+    assert_equal([["a", "http://www.w3.org/2000/svg", true, false]], seen_by("svg|a"))
+    assert_equal([["title", "http://www.w3.org/1999/xhtml", false, false]], seen_by("html|title"))
+    assert_equal(["math", "mi"], seen_by("math|*").map(&:first))
+    assert_equal(["a", "a"], seen_by("*|a").map(&:first))
+  end
+
+  def test_match_element_with_a_namespace_prefix_in_a_complex_selector
+    assert_equal([["title", "http://www.w3.org/2000/svg", true, false]], seen_by("svg > svg|title"))
+    assert_empty(seen_by("html|a > svg|title"))
+  end
+
+  def test_matched_selector_is_the_namespaced_one
+    matched = []
+    handler = Class.new do
+      define_method(:selector) { Selma::Selector.new(match_element: ["svg|a", "html|a"]) }
+      define_method(:handle_element) { |_element, selector| matched << selector }
+    end.new
+    Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite(HTML)
+
+    assert_equal(["html|a", "svg|a"], matched)
+  end
+
+  def test_ancestor_namespace
+    handler = RecordAncestors.new
+    Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite("<div><svg><g><path></path></g><foreignObject><em>a</em></foreignObject></svg></div>")
+
+    assert_equal(
+      [
+        [["div", false, false], ["svg", true, false], ["g", true, false]],
+        [["div", false, false], ["svg", true, false], ["foreignobject", true, false]],
+      ],
+      handler.seen,
+    )
+  end
+
+  def test_ignore_text_within_with_a_namespace_prefix
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [WrapText.new("svg|title")])
+
+    assert_equal(
+      "<title>DOC</title><svg><title>Icon</title></svg>",
+      rewriter.rewrite("<title>doc</title><svg><title>Icon</title></svg>"),
+    )
+  end
+
+  def test_inside_with_a_namespace_prefix
+    inside = []
+    handler = Class.new do
+      define_method(:selector) { Selma::Selector.new(match_element: "a") }
+      define_method(:handle_element) { |element| inside << element.inside?("svg|*") }
+    end.new
+    Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite(HTML)
+
+    assert_equal([false, true], inside)
+  end
+
+  def test_unknown_namespace_prefix
+    assert_raises(ArgumentError) { Selma::Selector.new(match_element: "xlink|a") }
+    assert_raises(ArgumentError) { Selma::Selector.new(match_text_within: "svg|text") }
+  end
+end
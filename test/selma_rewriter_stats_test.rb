@@ -0,0 +1,76 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+class SelmaRewriterStatsTest < Minitest::Test
+  class UpcaseText
+    SELECTOR = Selma::Selector.new(match_element: "p", match_text_within: "p")
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      element["class"] = "seen"
+    end
+
+    def handle_text_chunk(text)
+      text.replace(text.to_s.upcase, as: :text)
+    end
+  end
+
+  def test_stats_start_empty
+    handler = UpcaseText.new
+    stats = Selma::Rewriter.new(handlers: [handler]).stats
+
+    assert_equal(0, stats[:rewrites])
+    assert_in_delta(0.0, stats[:elapsed])
+    assert_equal({ calls: 0, elapsed: 0.0 }, stats[:sanitization])
+    assert_equal({ calls: 0, elapsed: 0.0 }, stats[:final_sanitization])
+    assert_equal([handler], stats[:handlers].map { |h| h[:handler] })
+  end
+
+  def test_stats_count_handler_calls
+    handler = UpcaseText.new
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [handler])
+
+    assert_equal("<p class=\"seen\">ONE</p><div>two</div><p class=\"seen\">THREE</p>", rewriter.rewrite("<p>one</p><div>two</div><p>three</p>"))
+
+    stats = rewriter.stats
+
+    assert_equal(1, stats[:rewrites])
+    assert_operator(stats[:elapsed], :>, 0.0)
+    assert_equal(0, stats[:sanitization][:calls])
+    assert_equal(0, stats[:final_sanitization][:calls])
+
+    handler_stats = stats[:handlers].first
+
+    assert_same(handler, handler_stats[:handler])
+    assert_equal(2, handler_stats[:handle_element][:calls])
+    assert_operator(handler_stats[:handle_text_chunk][:calls], :>=, 2)
+  end
+
+  def test_stats_track_sanitization
+    rewriter = Selma::Rewriter.new
+
+    rewriter.rewrite("<div><p>one</p><script>two</script></div>")
+
+    stats = rewriter.stats
+
+    assert_operator(stats[:sanitization][:calls], :>=, 3)
+    assert_equal(1, stats[:final_sanitization][:calls])
+    assert_empty(stats[:handlers])
+  end
+
+  def test_stats_are_cumulative
+    rewriter = Selma::Rewriter.new(handlers: [UpcaseText.new])
+
+    3.times { rewriter.rewrite("<p>hello</p>") }
+
+    stats = rewriter.stats
+
+    assert_equal(3, stats[:rewrites])
+    assert_equal(3, stats[:handlers].first[:handle_element][:calls])
+    assert_equal(3, stats[:final_sanitization][:calls])
+  end
+end
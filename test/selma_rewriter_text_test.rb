@@ -163,6 +163,58 @@ class SelmaRewriterTextTest < Minitest::Test
     assert_equal("<p>bar</p><code>foo<span class=\"highlight\">foo</span></code>", modified_doc)
   end
 
+  class RejectByCSS
+    SELECTOR = Selma::Selector.new(match_text_within: "*", ignore_text_within: "code, pre, a, .no-autolink, [data-skip]")
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_text_chunk(text)
+      text.replace(text.to_s.gsub("foo", "bar"), as: :text)
+    end
+  end
+
+  def test_that_text_reject_accepts_css
+    frag = %(<p>foo</p><a href="/">foo</a><div class="note no-autolink"><em>foo</em></div><p data-skip="">foo</p><p class="autolink">foo</p>)
+    modified_doc = Selma::Rewriter.new(sanitizer: nil, handlers: [RejectByCSS.new]).rewrite(frag)
+
+    assert_equal(%(<p>bar</p><a href="/">foo</a><div class="note no-autolink"><em>foo</em></div><p data-skip="">foo</p><p class="autolink">bar</p>), modified_doc)
+  end
+
+  class RejectByCombinators
+    SELECTOR = Selma::Selector.new(match_text_within: "*", ignore_text_within: ["div.docs > p", "[lang|=en] span", %(a[href^="https:" i])])
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_text_chunk(text)
+      text.replace(text.to_s.gsub("foo", "bar"), as: :text)
+    end
+  end
+
+  def test_that_text_reject_supports_combinators_and_attribute_operators
+    frag = %(<div class="docs"><p>foo</p><section><p>foo</p></section></div><div lang="en-US"><b><span>foo</span></b></div><a href="HTTPS://example.com">foo</a><a href="http://example.com">foo</a>)
+    modified_doc = Selma::Rewriter.new(sanitizer: nil, handlers: [RejectByCombinators.new]).rewrite(frag)
+
+    assert_equal(%(<div class="docs"><p>foo</p><section><p>bar</p></section></div><div lang="en-US"><b><span>foo</span></b></div><a href="HTTPS://example.com">foo</a><a href="http://example.com">bar</a>), modified_doc)
+  end
+
+  def test_that_text_reject_raises_on_unsupported_css
+    assert_raises(ArgumentError) do
+      Selma::Selector.new(match_text_within: "*", ignore_text_within: "p:first-child")
+    end
+
+    assert_raises(ArgumentError) do
+      Selma::Selector.new(match_text_within: "*", ignore_text_within: "h1 + p")
+    end
+
+    assert_raises(ArgumentError) do
+      Selma::Selector.new(match_text_within: "*", ignore_text_within: ["code", "[data-skip"])
+    end
+  end
+
   class TextRewriteOne
     SELECTOR = Selma::Selector.new(match_text_within: "*")
 
@@ -0,0 +1,100 @@
+# frozen_string_literal: true
+
+require "test_helper"
+require "timeout"
+
+class SelmaRewriterTimeoutTest < Minitest::Test
+  class Stop < Exception # rubocop:disable Lint/InheritException
+  end
+
+  class SlowHandler
+    SELECTOR = Selma::Selector.new(match_element: "p")
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      sleep(0.02)
+    end
+  end
+
+  class InterruptingHandler
+    SELECTOR = Selma::Selector.new(match_element: "p")
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      raise Interrupt
+    end
+  end
+
+  def test_timeout_must_be_positive
+    assert_raises(ArgumentError) do
+      Selma::Rewriter.new(options: { timeout: 0 })
+    end
+
+    assert_raises(ArgumentError) do
+      Selma::Rewriter.new(options: { timeout: -1.5 })
+    end
+
+    assert_raises(TypeError) do
+      Selma::Rewriter.new(options: { timeout: "soon" })
+    end
+  end
+
+  def test_timeout_is_not_hit_by_quick_rewrites
+    html = "<p>Hello</p>" * 5
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [SlowHandler.new], options: { timeout: 10 })
+
+    assert_equal(html, rewriter.rewrite(html))
+  end
+
+  def test_timeout_aborts_slow_rewrites
+    html = "<p>Hello</p>" * 100
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [SlowHandler.new], options: { timeout: 0.1 })
+
+    error = assert_raises(Selma::Rewriter::TimeoutError) do
+      rewriter.rewrite(html)
+    end
+
+    assert_match(/longer than 0.1s/, error.message)
+  end
+
+  def test_timeout_error_is_a_runtime_error
+    assert_operator(Selma::Rewriter::TimeoutError, :<, RuntimeError)
+  end
+
+  def test_ruby_timeouts_interrupt_rewrites
+    html = "<p>Hello</p>" * 100
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [SlowHandler.new])
+
+    assert_raises(Timeout::Error) do
+      Timeout.timeout(0.1) { rewriter.rewrite(html) }
+    end
+  end
+
+  def test_non_standard_errors_are_not_wrapped
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [InterruptingHandler.new])
+
+    assert_raises(Interrupt) do
+      rewriter.rewrite("<p>Hello</p>")
+    end
+  end
+
+  def test_thread_raise_interrupts_rewrites
+    html = "<p>Hello</p>" * 100
+    rewriter = Selma::Rewriter.new(sanitizer: nil, handlers: [SlowHandler.new])
+
+    thread = Thread.new do
+      Thread.current.report_on_exception = false
+      rewriter.rewrite(html)
+    end
+    sleep(0.1)
+    thread.raise(Stop, "stop")
+
+    assert_raises(Stop) { thread.join }
+  end
+end
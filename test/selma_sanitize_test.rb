@@ -0,0 +1,50 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizeTest < Minitest::Test
+    HTML = [
+      "<a href='https://google.com'>here is a neat site!</a>",
+      "<p onclick='steal()'>Hi <b>there</b></p><!-- comment -->",
+      "<!DOCTYPE html><div><script>alert(1)</script><img src=x onerror=alert(1)></div>",
+      "<a href='javascript:alert(1)'>click</a><iframe src='https://example.com'>x</iframe>",
+      "<<script>script>alert(1)<</script>/script>",
+    ]
+
+    def test_sanitize_matches_a_sanitizing_rewrite
+      [Selma::Sanitizer::Config::DEFAULT, Selma::Sanitizer::Config::BASIC, Selma::Sanitizer::Config::RELAXED].each do |config|
+        sanitizer = Selma::Sanitizer.new(config)
+        rewriter = Selma::Rewriter.new(sanitizer: sanitizer)
+
+        HTML.each do |html|
+          assert_equal(rewriter.rewrite(html), sanitizer.sanitize(html))
+        end
+      end
+    end
+
+    def test_sanitize
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)
+
+      assert_equal("<p>Hi</p>", sanitizer.sanitize("<p onclick='steal()'>Hi</p>"))
+      assert_equal("<b>bold</b>", sanitizer.sanitize("<b>bold</b><script>alert(1)</script>"))
+    end
+
+    def test_sanitize_can_be_called_repeatedly
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config::BASIC)
+
+      3.times do
+        assert_equal("<em>hi</em>", sanitizer.sanitize("<em>hi</em><div>"))
+      end
+    end
+
+    def test_selma_sanitize_defaults_to_the_default_config
+      assert_equal("bold", Selma.sanitize("<b>bold</b><script>alert(1)</script>"))
+    end
+
+    def test_selma_sanitize_with_a_config
+      assert_equal("<b>bold</b>", Selma.sanitize("<b>bold</b>", Selma::Sanitizer::Config::BASIC))
+      assert_equal("<b>bold</b>x", Selma.sanitize("<b>bold</b><i>x</i>", { elements: ["b"] }))
+    end
+  end
+end
@@ -0,0 +1,100 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerClobberProtectionTest < Minitest::Test
+    def test_it_prefixes_ids_and_names
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, clobber_protection: true))
+
+      assert_equal(
+        %(<p id="user-content-cookie">x</p><a name="user-content-config">y</a>),
+        sanitizer.sanitize(%(<p id="cookie">x</p><a name="config">y</a>)),
+      )
+    end
+
+    def test_it_rewrites_fragment_links_to_match
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, clobber_protection: true))
+
+      assert_equal(
+        %(<a href="#user-content-notes">x</a><a href="https://example.com/#notes">y</a><a href="#">z</a>),
+        sanitizer.sanitize(%(<a href="#notes">x</a><a href="https://example.com/#notes">y</a><a href="#">z</a>)),
+      )
+    end
+
+    def test_it_uses_the_given_prefix
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, id_prefix: "readme-"))
+
+      assert_equal(
+        %(<h2 id="readme-usage">Usage</h2><a href="#readme-usage">x</a>),
+        sanitizer.sanitize(%(<h2 id="usage">Usage</h2><a href="#usage">x</a>)),
+      )
+    end
+
+    def test_it_does_not_prefix_twice
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, clobber_protection: true))
+      html = %(<p id="user-content-cookie">x</p><a href="#user-content-cookie">y</a>)
+
+      assert_equal(html, sanitizer.sanitize(html))
+      assert_equal(html, sanitizer.sanitize(sanitizer.sanitize(%(<p id="cookie">x</p><a href="#cookie">y</a>))))
+    end
+
+    def test_it_prefixes_references_to_ids
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        clobber_protection: true,
+        attributes: { all: ["id", "aria-labelledby"] },
+      )
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(
+        %(<table><tr><th id="user-content-a">A</th><td headers="user-content-a user-content-b" aria-labelledby="user-content-a">1</td></tr></table>),
+        sanitizer.sanitize(%(<table><tr><th id="a">A</th><td headers="a b" aria-labelledby="a">1</td></tr></table>)),
+      )
+    end
+
+    def test_it_prefixes_ids_in_foreign_content
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::FOREIGN_CONTENT, clobber_protection: true))
+
+      assert_equal(
+        %(<svg><linearGradient id="user-content-g"></linearGradient><path fill="url(#user-content-g)"></path><use href="#user-content-icon"></use></svg>),
+        sanitizer.sanitize(%(<svg><linearGradient id="g"></linearGradient><path fill="url(#g)"></path><use href="#icon"></use></svg>)),
+      )
+    end
+
+    def test_it_only_prefixes_url_references_in_svg_paint_and_reference_attributes
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::FOREIGN_CONTENT, clobber_protection: true))
+
+      assert_equal(
+        %(<svg><path stroke="url(#user-content-g)" aria-label="url(#g)"></path></svg><p title="url(#g)">x</p>),
+        sanitizer.sanitize(%(<svg><path stroke="url(#g)" aria-label="url(#g)"></path></svg><p title="url(#g)">x</p>)),
+      )
+    end
+
+    def test_heading_ids_share_the_prefix
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, clobber_protection: true, headings: { ids: true })
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(
+        %(<h2 id="user-content-install">Install</h2><a href="#user-content-install">x</a>),
+        sanitizer.sanitize(%(<h2>Install</h2><a href="#install">x</a>)),
+      )
+    end
+
+    def test_it_is_off_by_default_and_can_be_turned_off
+      html = %(<p id="cookie">x</p>)
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, id_prefix: "readme-", clobber_protection: false)
+
+      assert_equal(html, Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED).sanitize(html))
+      assert_equal(html, Selma::Sanitizer.new(config).sanitize(html))
+    end
+
+    def test_it_checks_the_options
+      [{ clobber_protection: "yes" }, { id_prefix: :user }, { id_prefix: "" }].each do |options|
+        config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, options)
+
+        assert_raises(ArgumentError, options.inspect) { Selma::Sanitizer.new(config) }
+      end
+    end
+  end
+end
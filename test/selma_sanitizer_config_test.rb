@@ -8,6 +8,7 @@ module Selma
       verify_deeply_frozen(Selma::Sanitizer::Config::DEFAULT)
       verify_deeply_frozen(Selma::Sanitizer::Config::BASIC)
       verify_deeply_frozen(Selma::Sanitizer::Config::RELAXED)
+      verify_deeply_frozen(Selma::Sanitizer::Config::FOREIGN_CONTENT)
       verify_deeply_frozen(Selma::Sanitizer::Config::RESTRICTED)
     end
 
@@ -53,5 +54,87 @@ module Selma
       assert_raises(ArgumentError) { Selma::Sanitizer::Config.merge("foo", {}) }
       assert_raises(ArgumentError) { Selma::Sanitizer::Config.merge({}, "foo") }
     end
+
+    def test_built_in_configs_are_valid
+      [:DEFAULT, :BASIC, :RELAXED, :FOREIGN_CONTENT, :RESTRICTED].each do |name|
+        assert_instance_of(Selma::Sanitizer, Selma::Sanitizer.new(Selma::Sanitizer::Config.const_get(name)))
+      end
+    end
+
+    def test_unknown_keys_raise_with_a_suggestion
+      error = assert_raises(ArgumentError) do
+        Selma::Sanitizer.new({ elements: ["div"], remove_content: ["script"] })
+      end
+
+      assert_equal("unknown key `:remove_content` in sanitizer config. Did you mean `:remove_contents`?", error.message)
+
+      error = assert_raises(ArgumentError) { Selma::Sanitizer.new({ attribute: { "a" => ["href"] } }) }
+
+      assert_match("Did you mean `:attributes`?", error.message)
+    end
+
+    def test_unknown_keys_without_a_close_match_raise
+      error = assert_raises(ArgumentError) { Selma::Sanitizer.new({ transformers: [] }) }
+
+      assert_equal("unknown key `:transformers` in sanitizer config.", error.message)
+    end
+
+    def test_string_keys_raise
+      error = assert_raises(ArgumentError) { Selma::Sanitizer.new({ "elements" => ["div"] }) }
+
+      assert_equal('sanitizer config keys must be Symbols; found `"elements"`. Did you mean `:elements`?', error.message)
+    end
+
+    def test_non_string_element_names_raise
+      error = assert_raises(ArgumentError) { Selma::Sanitizer.new({ elements: ["div", 1] }) }
+
+      assert_equal("`elements` must only contain Strings or Symbols; found `1`", error.message)
+    end
+
+    def test_mistyped_values_raise
+      error = assert_raises(ArgumentError) { Selma::Sanitizer.new({ allow_comments: "no" }) }
+
+      assert_equal('`allow_comments` must be `true` or `false`, not `"no"`', error.message)
+
+      error = assert_raises(ArgumentError) { Selma::Sanitizer.new({ attributes: { "a" => "href" } }) }
+
+      assert_equal('`attributes["a"]` must be an Array of Strings or Symbols, not `"href"`', error.message)
+
+      error = assert_raises(ArgumentError) { Selma::Sanitizer.new({ protocols: { "a" => ["https"] } }) }
+
+      assert_equal('`protocols["a"]` must be a Hash, not `["https"]`', error.message)
+    end
+
+    def test_foreign_content_keys_are_checked
+      error = assert_raises(ArgumentError) do
+        Selma::Sanitizer.new({ foreign_content: { svg: { element: ["svg"] } } })
+      end
+
+      assert_equal("unknown key `:element` in `foreign_content[:svg]`. Did you mean `:elements`?", error.message)
+
+      error = assert_raises(ArgumentError) do
+        Selma::Sanitizer.new({ foreign_content: { mathml: { elements: ["math"] } } })
+      end
+
+      assert_match("Did you mean `:math`?", error.message)
+    end
+
+    def test_data_is_accepted_in_attribute_lists
+      assert_instance_of(Selma::Sanitizer, Selma::Sanitizer.new({ elements: ["div"], attributes: { "div" => [:data] } }))
+    end
+
+    def test_data_as_a_string_raises
+      error = assert_raises(ArgumentError) do
+        Selma::Sanitizer.new({ elements: ["div"], attributes: { "div" => ["DATA"] } })
+      end
+
+      assert_match("`:data`", error.message)
+    end
+
+    def test_strict_false_skips_validation
+      sanitizer = Selma::Sanitizer.new({ strict: false, elements: ["b", 1], remove_content: ["script"] })
+
+      assert_equal("<b>bold</b> italic", Selma::Rewriter.new(sanitizer: sanitizer).rewrite("<b>bold</b> <i>italic</i>"))
+    end
   end
 end
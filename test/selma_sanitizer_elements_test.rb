@@ -279,6 +279,15 @@ module Selma
           )
         end
 
+        def test_should_only_clear_remove_contents_when_remove_contents_is_false
+          sanitizer = Selma::Sanitizer.new({ elements: ["b"], remove_contents: false, whitespace_elements: ["div"] })
+
+          assert_equal(
+            "foo <b>bar</b> baz ",
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite("foo <b>bar</b><div>baz</div>"),
+          )
+        end
+
         def test_remove_the_contents_of_specified_nodes_when_remove_contents_is_an_array_of_element_names_as_strings
           sanitizer = Selma::Sanitizer.new({ remove_contents: ["script", "span"] })
 
@@ -381,6 +390,48 @@ module Selma
           assert_equal("<a>Text</a>", Selma::Rewriter.new(sanitizer: sanitizer).rewrite(input))
         end
 
+        def test_should_accept_symbols_as_element_and_attribute_names
+          sanitizer = Selma::Sanitizer.new(
+            elements: [:a, :b],
+            attributes: { a: [:href, :title] },
+            protocols: { a: { href: ["https"] } },
+          )
+
+          assert_equal(
+            '<b><a href="https://foo.com/" title="Foo">Text</a></b>',
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite('<b><a href="https://foo.com/" title="Foo" rel="me">Text</a></b><i>!</i>'),
+          )
+          assert_equal(
+            "<a>Text</a>",
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite('<a href="javascript:alert(1)">Text</a>'),
+          )
+        end
+
+        def test_should_normalize_the_case_of_element_and_attribute_names
+          sanitizer = Selma::Sanitizer.new(
+            elements: ["DIV", :Span],
+            attributes: { "Div" => ["ID"], all: [:Lang] },
+            remove_contents: [:SCRIPT],
+          )
+
+          assert_equal(
+            '<DIV id="foo" lang="en"><Span LANG="fr">hi</Span></DIV>',
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite('<DIV id="foo" lang="en"><Span LANG="fr">hi</Span><script>alert(1)</script></DIV>'),
+          )
+        end
+
+        def test_should_allow_any_data_attribute_with_the_data_symbol
+          sanitizer = Selma::Sanitizer.new(
+            elements: ["b"],
+            attributes: { "b" => [:data] },
+          )
+
+          assert_equal(
+            '<b data-foo="1" data-bar-baz="2"></b>',
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite('<b data-foo="1" data-bar-baz="2" data="3" title="4"></b>'),
+          )
+        end
+
         def test_should_sanitize_protocols_in_data_attributes_even_if_data_attributes_are_generically_allowed
           input = '<a data-url="mailto:someone@example.com">Text</a>'
 
@@ -461,6 +512,74 @@ module Selma
           )
         end
 
+        def test_should_allow_unknown_elements_individually
+          sanitizer = Selma::Sanitizer.new(elements: ["my-widget", "foo"])
+
+          assert_equal(
+            "<my-widget>a</my-widget>b<foo>c</foo>d",
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite("<my-widget>a</my-widget><my-other-widget>b</my-other-widget><foo>c</foo><bar>d</bar>"),
+          )
+        end
+
+        def test_should_remove_contents_of_unknown_elements_individually
+          sanitizer = Selma::Sanitizer.new(remove_contents: ["my-ad"], whitespace_elements: ["my-block"])
+
+          assert_equal(
+            "a b c",
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite("<my-ad>ad</my-ad>a<my-block>b</my-block>c<my-widget></my-widget>"),
+          )
+        end
+
+        def test_should_remove_contents_of_all_elements_including_unknown_ones
+          sanitizer = Selma::Sanitizer.new(remove_contents: true, elements: ["p"], whitespace_elements: ["my-block"])
+
+          assert_equal(
+            "<p>kept</p>",
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite("<p>kept</p><my-block>gone</my-block><span>gone</span>"),
+          )
+        end
+
+        def test_should_allow_all_custom_elements
+          sanitizer = Selma::Sanitizer.new(elements: ["p"], custom_elements: :allow)
+
+          assert_equal(
+            "<p><my-widget><x-y>a</x-y></my-widget>b</p>",
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite("<p><my-widget><x-y>a</x-y></my-widget><foo>b</foo></p>"),
+          )
+        end
+
+        def test_should_not_allow_reserved_names_as_custom_elements
+          sanitizer = Selma::Sanitizer.new(custom_elements: :allow)
+
+          assert_equal(
+            "",
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite("<font-face></font-face><annotation-xml></annotation-xml>"),
+          )
+        end
+
+        def test_should_allow_listed_custom_elements
+          sanitizer = Selma::Sanitizer.new(custom_elements: ["my-widget"])
+
+          assert_equal(
+            "<my-widget>a</my-widget>b",
+            Selma::Rewriter.new(sanitizer: sanitizer).rewrite("<my-widget>a</my-widget><my-gadget>b</my-gadget>"),
+          )
+        end
+
+        def test_should_reject_invalid_custom_elements_config
+          assert_raises(ArgumentError) do
+            Selma::Sanitizer.new(custom_elements: :sometimes)
+          end
+
+          assert_raises(ArgumentError) do
+            Selma::Sanitizer.new(custom_elements: ["widget"])
+          end
+
+          assert_raises(ArgumentError) do
+            Selma::Sanitizer.new(custom_elements: "my-widget")
+          end
+        end
+
         def test_should_not_modify_meta_tags_that_already_set_a_utf8_charset
           skip("non-essential feature")
 
@@ -0,0 +1,112 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerForeignContentTest < Minitest::Test
+    def setup
+      @rewriter = Selma::Rewriter.new(sanitizer: Selma::Sanitizer.new(Selma::Sanitizer::Config::FOREIGN_CONTENT))
+    end
+
+    def test_svg_is_still_removed_without_foreign_content_rules
+      html = '<svg viewBox="0 0 16 16"><path d="M0 0h16v16H0z"></path></svg>'
+
+      assert_equal("", Selma::Rewriter.new(sanitizer: Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)).rewrite(html))
+    end
+
+    def test_keeps_inline_svg_icons
+      html = '<p><svg viewBox="0 0 16 16" aria-hidden="true"><path d="M0 0h16v16H0z" fill="currentColor"></path></svg> Done</p>'
+
+      assert_equal(html, @rewriter.rewrite(html))
+    end
+
+    def test_keeps_svg_titles_through_the_final_sanitization
+      html = "<svg><title>Checkmark</title></svg>"
+
+      assert_equal(html, @rewriter.rewrite(html))
+    end
+
+    def test_removes_foreign_object_and_its_contents
+      html = '<svg><foreignObject><p>hi</p><img src="x"></foreignObject><circle r="1"></circle></svg>'
+
+      assert_equal('<svg><circle r="1"></circle></svg>', @rewriter.rewrite(html))
+    end
+
+    def test_removes_disallowed_svg_elements_with_their_contents
+      html = '<svg><script>alert(1)</script><animate attributeName="href" to="javascript:alert(1)"></animate><g></g></svg>'
+
+      assert_equal("<svg><g></g></svg>", @rewriter.rewrite(html))
+    end
+
+    def test_strips_javascript_links
+      html = '<svg><a xlink:href="javascript:alert(1)"><text>hi</text></a><a href="javascript:alert(1)"></a></svg>'
+
+      assert_equal("<svg><a><text>hi</text></a><a></a></svg>", @rewriter.rewrite(html))
+    end
+
+    def test_keeps_links_with_allowed_protocols
+      html = '<svg><a href="https://example.com"><text>hi</text></a></svg>'
+
+      assert_equal(html, @rewriter.rewrite(html))
+    end
+
+    def test_references_only_point_within_the_document
+      assert_equal('<svg><use href="#icon"></use></svg>', @rewriter.rewrite('<svg><use href="#icon"></use></svg>'))
+      assert_equal("<svg><use></use></svg>", @rewriter.rewrite('<svg><use xlink:href="data:image/svg+xml,&lt;svg&gt;"></use></svg>'))
+      assert_equal("<svg><use></use></svg>", @rewriter.rewrite('<svg><use href="https://example.com/sprite.svg#icon"></use></svg>'))
+    end
+
+    def test_keeps_mathml
+      html = "<math><mi>x</mi><mo>=</mo><mfrac><mn>1</mn><mn>2</mn></mfrac></math>"
+
+      assert_equal(html, @rewriter.rewrite(html))
+    end
+
+    def test_removes_annotation_xml
+      html = '<math><semantics><mi>x</mi><annotation-xml encoding="text/html"><p>hi</p></annotation-xml></semantics></math>'
+
+      assert_equal("<math><semantics><mi>x</mi></semantics></math>", @rewriter.rewrite(html))
+    end
+
+    def test_html_inside_mathml_follows_the_html_rules
+      html = "<math><mtext><b>x</b><script>alert(1)</script></mtext></math>"
+
+      assert_equal("<math><mtext><b>x</b></mtext></math>", @rewriter.rewrite(html))
+    end
+
+    def test_html_inside_svg_descriptions_follows_the_html_rules
+      html = %(<svg><desc><b onclick="alert(1)">x</b><script>alert(1)</script></desc></svg>)
+
+      assert_equal("<svg><desc><b>x</b></desc></svg>", @rewriter.rewrite(html))
+    end
+
+    def test_html_rules_do_not_apply_inside_svg
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::DEFAULT,
+        elements: ["a"],
+        attributes: { "a" => ["href"] },
+        protocols: { "a" => { "href" => ["https"] } },
+        foreign_content: { svg: { elements: ["svg"] } },
+      )
+      rewriter = Selma::Rewriter.new(sanitizer: Selma::Sanitizer.new(config))
+
+      assert_equal("<svg></svg>", rewriter.rewrite('<svg><a href="https://example.com">hi</a></svg>'))
+    end
+
+    def test_namespaces_without_rules_are_removed
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        foreign_content: { svg: { elements: ["svg"] } },
+      )
+      rewriter = Selma::Rewriter.new(sanitizer: Selma::Sanitizer.new(config))
+
+      assert_equal("<svg></svg>", rewriter.rewrite("<svg></svg><math><mi>x</mi></math>"))
+    end
+
+    def test_foreign_content_must_be_a_hash
+      assert_raises(ArgumentError) do
+        Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::DEFAULT, foreign_content: [:svg]))
+      end
+    end
+  end
+end
@@ -0,0 +1,136 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerHeadingsTest < Minitest::Test
+    def test_it_shifts_headings_and_clamps_them_to_h6
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { shift: 2 }))
+
+      assert_equal(
+        %(<h3>Title</h3><h4>Intro</h4><h6>Details</h6><h6>More</h6><p>text</p>),
+        sanitizer.sanitize(%(<h1>Title</h1><h2>Intro</h2><h4>Details</h4><h6>More</h6><p>text</p>)),
+      )
+    end
+
+    def test_it_shifts_headings_up_and_clamps_them_to_h1
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { shift: -1 }))
+
+      assert_equal(%(<h1>Title</h1><h1>Intro</h1>), sanitizer.sanitize(%(<h1>Title</h1><h2>Intro</h2>)))
+    end
+
+    def test_it_shifts_after_renaming
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { shift: 1 }, rename_elements: { "h1" => "h3" })
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(%(<h4>Title</h4><h3>Intro</h3>), sanitizer.sanitize(%(<h1>Title</h1><h2>Intro</h2>)))
+    end
+
+    def test_it_gives_headings_prefixed_slug_ids
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { ids: true }))
+
+      assert_equal(
+        %(<h1 id="user-content-getting-started">Getting Started!</h1>) +
+          %(<h2 id="user-content-déjà-vu--more">Déjà vu &amp; <code>more</code></h2>),
+        sanitizer.sanitize(%(<h1>Getting Started!</h1><h2>Déjà vu &amp; <code>more</code></h2>)),
+      )
+    end
+
+    def test_it_deduplicates_ids_across_the_document
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { ids: true, id_prefix: "" }))
+
+      assert_equal(
+        %(<h2 id="usage">Usage</h2><h3 id="usage-1">Usage</h3><h3 id="usage-1-1">Usage 1</h3><h3 id="usage-2">Usage</h3>),
+        sanitizer.sanitize(%(<h2>Usage</h2><h3>Usage</h3><h3>Usage 1</h3><h3>Usage</h3>)),
+      )
+    end
+
+    def test_it_replaces_existing_ids_and_names_empty_headings
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { ids: true }, attributes: { all: ["id"] })
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(
+        %(<h1 id="user-content-section"> </h1><h2 id="user-content-title">Title</h2>),
+        sanitizer.sanitize(%(<h1 id="logo"> </h1><h2 id="clobber">Title</h2>)),
+      )
+    end
+
+    def test_ids_leave_out_removed_content
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { ids: true }))
+
+      assert_equal(
+        %(<h1 id="user-content-title">Title</h1>),
+        sanitizer.sanitize(%(<h1>Title<script>alert("x")</script></h1>)),
+      )
+    end
+
+    def test_rewriters_give_ids_from_the_handled_text
+      handler = Class.new do
+        def selector
+          Selma::Selector.new(match_text_within: "h1")
+        end
+
+        def handle_text_chunk(text)
+          text.replace(text.to_s.upcase, as: :text)
+        end
+      end.new
+
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { shift: 1, ids: true }))
+      result = Selma::Rewriter.new(sanitizer: sanitizer, handlers: [handler]).rewrite(%(<h1>Title</h1><h1>Title</h1>))
+
+      assert_equal(%(<h2 id="user-content-title">TITLE</h2><h2 id="user-content-title-1">TITLE</h2>), result)
+    end
+
+    def test_headings_the_final_pass_removes_do_not_shift_the_ids_after_them
+      handler = Class.new do
+        def selector
+          Selma::Selector.new(match_element: "p")
+        end
+
+        def handle_element(element)
+          element.after("<svg><title><h2>Removed</h2></title></svg>", as: :html)
+        end
+      end.new
+
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { ids: true }))
+      result = Selma::Rewriter.new(sanitizer: sanitizer, handlers: [handler]).rewrite(%(<p>x</p><h2>Kept</h2><h2>Also kept</h2>))
+
+      assert_equal(
+        %(<p>x</p><svg></svg><h2 id="user-content-kept">Kept</h2><h2 id="user-content-also-kept">Also kept</h2>),
+        result,
+      )
+    end
+
+    def test_nested_headings_give_the_outer_one_the_text_after_them
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { ids: true }))
+
+      assert_equal(
+        %(<h1 id="user-content-before--after"> before <h2 id="user-content-inner">inner</h2> after</h1>),
+        sanitizer.sanitize(%(<h1> before <h2>inner</h2> after</h1>)),
+      )
+    end
+
+    def test_ids_share_a_custom_clobber_protection_prefix
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { ids: true }, id_prefix: "post-1-")
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(%(<h1 id="post-1-title">Title</h1>), sanitizer.sanitize(%(<h1>Title</h1>)))
+    end
+
+    def test_huge_shifts_are_clamped
+      [[(2**63) - 1, "h6"], [-2**63, "h1"]].each do |shift, tag_name|
+        sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: { shift: shift }))
+
+        assert_equal(%(<#{tag_name}>Title</#{tag_name}>), sanitizer.sanitize(%(<h3>Title</h3>)), shift)
+      end
+    end
+
+    def test_it_checks_the_options
+      [{ shfit: 1 }, { shift: "1" }, { ids: "yes" }, { ids: true, id_prefix: :user }, 1].each do |headings|
+        config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, headings: headings)
+
+        assert_raises(ArgumentError, headings.inspect) { Selma::Sanitizer.new(config) }
+      end
+    end
+  end
+end
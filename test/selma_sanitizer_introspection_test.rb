@@ -0,0 +1,70 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerIntrospectionTest < Minitest::Test
+    def setup
+      @sanitizer = Selma::Sanitizer.new(
+        Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::DEFAULT, Selma::Sanitizer::Config::BASIC),
+      )
+    end
+
+    def test_allowed_elements
+      assert_equal(Selma::Sanitizer::Config::BASIC[:elements].sort, @sanitizer.allowed_elements)
+    end
+
+    def test_allowed_elements_includes_configured_custom_elements
+      sanitizer = Selma::Sanitizer.new(elements: [:P, "My-Widget"], custom_elements: ["other-widget"])
+
+      assert_equal(["my-widget", "other-widget", "p"], sanitizer.allowed_elements)
+    end
+
+    def test_allowed
+      assert(@sanitizer.allowed?("a"))
+      assert(@sanitizer.allowed?(:STRONG))
+      refute(@sanitizer.allowed?("script"))
+      refute(@sanitizer.allowed?("my-widget"))
+    end
+
+    def test_allowed_follows_the_custom_elements_policy
+      sanitizer = Selma::Sanitizer.new(elements: ["p"], custom_elements: :allow)
+
+      assert(sanitizer.allowed?("my-widget"))
+      refute(sanitizer.allowed?("blink"))
+    end
+
+    def test_allowed_requires_a_name
+      assert_raises(ArgumentError) { @sanitizer.allowed?(1) }
+    end
+
+    def test_remove_contents_elements
+      assert_equal(Selma::Sanitizer::Config::DEFAULT[:remove_contents].sort, @sanitizer.remove_contents_elements)
+    end
+
+    def test_allowed_attributes
+      sanitizer = Selma::Sanitizer.new(
+        elements: ["a", "p"],
+        attributes: { all: ["title", :data], "a" => ["href", "title"], p: [:LANG] },
+      )
+
+      assert_equal(["data-*", "title"], sanitizer.allowed_attributes)
+      assert_equal(["data-*", "href", "title"], sanitizer.allowed_attributes(for: "a"))
+      assert_equal(["data-*", "lang", "title"], sanitizer.allowed_attributes(for: :p))
+      assert_equal(["data-*", "title"], sanitizer.allowed_attributes(for: "span"))
+    end
+
+    def test_allowed_protocols
+      assert_equal(["ftp", "http", "https", "mailto", :relative], @sanitizer.allowed_protocols("a", "href"))
+      assert_equal(["http", "https", :relative], @sanitizer.allowed_protocols(:q, :cite))
+      assert_nil(@sanitizer.allowed_protocols("abbr", "title"))
+      assert_nil(@sanitizer.allowed_protocols("my-widget", "href"))
+    end
+
+    def test_allowed_protocols_all
+      sanitizer = Selma::Sanitizer.new(elements: ["a"], protocols: { "a" => { "href" => :all } })
+
+      assert_equal(:all, sanitizer.allowed_protocols("a", "href"))
+    end
+  end
+end
@@ -0,0 +1,106 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerLinkPolicyTest < Minitest::Test
+    def test_it_marks_external_links
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, link_policy: { internal_hosts: ["example.com"] }))
+
+      assert_equal(
+        %(<a href="https://other.example/" rel="nofollow ugc noopener">x</a>),
+        sanitizer.sanitize(%(<a href="https://other.example/">x</a>)),
+      )
+    end
+
+    def test_it_leaves_internal_links_alone
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        link_policy: { internal_hosts: ["example.com", "*.example.org"], target: "_blank" },
+      )
+      sanitizer = Selma::Sanitizer.new(config)
+
+      [
+        %(<a href="https://example.com/about">x</a>),
+        %(<a href="HTTPS://EXAMPLE.COM:8443/about">x</a>),
+        %(<a href="https://docs.example.org/">x</a>),
+        %(<a href="/about">x</a>),
+        %(<a href="#top">x</a>),
+        %(<a href="mailto:someone@other.example">x</a>),
+        %(<a>x</a>),
+      ].each do |html|
+        assert_equal(html, sanitizer.sanitize(html), html)
+      end
+    end
+
+    def test_subdomain_patterns_do_not_match_the_domain_itself_or_lookalikes
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, link_policy: { internal_hosts: ["*.example.org"] }))
+
+      ["https://example.org/", "https://badexample.org/", "https://example.org.evil.example/"].each do |href|
+        assert_equal(
+          %(<a href="#{href}" rel="nofollow ugc noopener">x</a>),
+          sanitizer.sanitize(%(<a href="#{href}">x</a>)),
+          href,
+        )
+      end
+    end
+
+    def test_it_sees_through_userinfo_and_protocol_relative_urls
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, link_policy: { internal_hosts: ["example.com"] }))
+
+      ["https://example.com@evil.example/", "//evil.example/", "https://evil.example\\@example.com/"].each do |href|
+        assert_match(/rel="nofollow ugc noopener"/, sanitizer.sanitize(%(<a href="#{href}">x</a>)), href)
+      end
+    end
+
+    def test_it_skips_every_slash_after_the_scheme_like_browsers_do
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, link_policy: { internal_hosts: ["example.com"] }))
+
+      ["https:/evil.example/", "https:///evil.example/", "http:\\\\/evil.example/", "https:evil.example"].each do |href|
+        assert_match(/rel="nofollow ugc noopener"/, sanitizer.sanitize(%(<a href="#{href}">x</a>)), href)
+      end
+
+      assert_equal(%(<a href="https:///example.com/">x</a>), sanitizer.sanitize(%(<a href="https:///example.com/">x</a>)))
+    end
+
+    def test_it_counts_links_whose_host_cannot_be_made_out_as_external
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, link_policy: { internal_hosts: ["example.com"] }))
+
+      ["https://", "https:///", "http://@/about"].each do |href|
+        assert_match(/rel="nofollow ugc noopener"/, sanitizer.sanitize(%(<a href="#{href}">x</a>)), href)
+      end
+    end
+
+    def test_it_merges_rel_and_sets_target
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        link_policy: { rel: ["nofollow", "noopener", "noreferrer"], target: "_blank" },
+      )
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(
+        %(<a href="https://other.example/" rel="author NOFOLLOW noopener noreferrer" target="_blank">x</a>),
+        sanitizer.sanitize(%(<a href="https://other.example/" rel="author NOFOLLOW">x</a>)),
+      )
+    end
+
+    def test_it_judges_links_before_they_are_rewritten
+      config = Selma::Sanitizer::Config
+      sanitizer = Selma::Sanitizer.new(config.merge(
+        config::RELAXED,
+        base_url: "https://example.com/",
+        link_policy: { internal_hosts: ["example.com"] },
+      ))
+
+      assert_equal(%(<a href="https://example.com/about">x</a>), sanitizer.sanitize(%(<a href="/about">x</a>)))
+    end
+
+    def test_it_checks_the_policy
+      [{ internal_host: ["example.com"] }, { internal_hosts: "example.com" }, { target: :blank }, ["example.com"]].each do |link_policy|
+        config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, link_policy: link_policy)
+
+        assert_raises(ArgumentError) { Selma::Sanitizer.new(config) }
+      end
+    end
+  end
+end
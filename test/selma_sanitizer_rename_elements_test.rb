@@ -0,0 +1,93 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerRenameElementsTest < Minitest::Test
+    def test_it_renames_elements_and_their_end_tags
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        rename_elements: { "b" => "strong", center: :div, "FONT" => "span", "h1" => "h3" },
+      )
+      sanitizer = Selma::Sanitizer.new(config)
+      html = %(<h1>Title</h1><center>Hi <b>there</b>, <font color="red">you</font></center>)
+
+      assert_equal(
+        %(<h3>Title</h3><div>Hi <strong>there</strong>, <span>you</span></div>),
+        sanitizer.sanitize(html),
+      )
+    end
+
+    def test_renamed_elements_are_judged_by_their_new_name
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        rename_elements: { "center" => "marquee", "h1" => "h3" },
+        attributes: { "h3" => ["data-level"] },
+      )
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(
+        %(hi <h3 data-level="1">Title</h3>),
+        sanitizer.sanitize(%(<center>hi</center> <h1 data-level="1">Title</h1>)),
+      )
+    end
+
+    def test_handlers_see_the_new_name
+      handler = Class.new do
+        attr_reader :tag_names
+
+        def initialize
+          @tag_names = []
+        end
+
+        def selector
+          Selma::Selector.new(match_element: "*")
+        end
+
+        def handle_element(element)
+          @tag_names << element.tag_name
+        end
+      end.new
+
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, rename_elements: { "b" => "strong" }))
+      result = Selma::Rewriter.new(sanitizer: sanitizer, handlers: [handler]).rewrite("<b>x</b>")
+
+      assert_equal("<strong>x</strong>", result)
+      assert_equal(["strong"], handler.tag_names)
+    end
+
+    def test_it_leaves_foreign_content_alone
+      config = Selma::Sanitizer::Config
+      sanitizer = Selma::Sanitizer.new(config.merge(config::FOREIGN_CONTENT, rename_elements: { "a" => "span" }))
+
+      assert_equal(
+        %(<span>x</span><svg><a href="#icon"></a></svg>),
+        sanitizer.sanitize(%(<a href="https://example.com/">x</a><svg><a href="#icon"></a></svg>)),
+      )
+    end
+
+    def test_it_refuses_renames_that_would_change_how_contents_are_parsed
+      [
+        { "xmp" => "pre" },
+        { "b" => "script" },
+        { "textarea" => "div" },
+        { "div" => "svg" },
+        { "br" => "span" },
+        { "span" => "img" },
+        { "b" => "not a name" },
+        { "b" => "nonsense" },
+      ].each do |rename_elements|
+        config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, rename_elements: rename_elements)
+
+        assert_raises(ArgumentError, rename_elements.inspect) { Selma::Sanitizer.new(config) }
+      end
+    end
+
+    def test_it_allows_swapping_void_elements
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, rename_elements: { "hr" => "br" }, elements: ["br"])
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal("a<br>b", sanitizer.sanitize("a<hr>b"))
+    end
+  end
+end
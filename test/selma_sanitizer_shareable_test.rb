@@ -0,0 +1,70 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerShareableTest < Minitest::Test
+    SANITIZER = Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)
+
+    def test_sanitizers_are_frozen_and_shareable
+      assert_predicate(SANITIZER, :frozen?)
+      assert(Ractor.shareable?(SANITIZER))
+    end
+
+    def test_config_is_deeply_frozen_without_freezing_the_callers
+      config = { elements: ["a"], attributes: { "a" => ["href"] } }
+      sanitizer = Selma::Sanitizer.new(config)
+
+      refute_predicate(config, :frozen?)
+      refute_predicate(config[:attributes]["a"], :frozen?)
+      assert_equal(config, sanitizer.config)
+      assert(Ractor.shareable?(sanitizer))
+    end
+
+    def test_frozen_configs_are_used_as_they_are
+      assert_same(Selma::Sanitizer::Config::RELAXED, SANITIZER.config)
+    end
+
+    def test_changing_a_config_afterwards_does_not_change_the_sanitizer
+      config = { elements: ["b"] }
+      sanitizer = Selma::Sanitizer.new(config)
+      config[:elements] << "i"
+
+      assert_equal("<b>b</b>i", sanitizer.sanitize("<b>b</b><i>i</i>"))
+    end
+
+    def test_one_sanitizer_across_threads
+      html = "<p onclick='x()'>Hi <script>alert(1)</script><b>there</b></p>"
+      expected = SANITIZER.sanitize(html)
+
+      results = Array.new(8) do
+        Thread.new { Array.new(50) { Selma::Rewriter.new(sanitizer: SANITIZER).rewrite(html) } }
+      end.flat_map(&:value)
+
+      assert(results.all?(expected))
+    end
+
+    def test_one_sanitizer_across_ractors
+      html = "<p onclick='x()'>Hi <b>there</b></p>"
+      expected = SANITIZER.sanitize(html)
+
+      results = without_experimental_warnings do
+        Array.new(4) { Ractor.new(html) { |h| SANITIZER.sanitize(h) } }.map do |ractor|
+          ractor.respond_to?(:value) ? ractor.value : ractor.take
+        end
+      end
+
+      assert_equal([expected] * 4, results)
+    end
+
+    private
+
+    def without_experimental_warnings
+      experimental = Warning[:experimental]
+      Warning[:experimental] = false
+      yield
+    ensure
+      Warning[:experimental] = experimental
+    end
+  end
+end
@@ -60,6 +60,17 @@ module Selma
       assert_equal("<a href=\"https://google.com\">wow!</a>", result)
     end
 
+    def test_it_can_keep_many_attributes
+      names = Array.new(150) { |i| "x-attr-#{i}" }
+      sanitizer = Selma::Sanitizer.new(elements: ["p"], attributes: { all: names.first(100), "p" => names.last(50) })
+      html = %(<p x-attr-149="a" x-attr-0="b" x-attr-64="c" title="d" x-attr-99="e" x-attr-150="f">hi</p>)
+
+      assert_equal(
+        %(<p x-attr-149="a" x-attr-0="b" x-attr-64="c" x-attr-99="e">hi</p>),
+        sanitizer.sanitize(html),
+      )
+    end
+
     def test_it_can_be_turned_off
       html = '<a href="https://google.com">wow!</a>'
       assert_raises(ArgumentError) do
@@ -0,0 +1,133 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerUrlListTest < Minitest::Test
+    def test_it_checks_each_srcset_candidate
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)
+      html = %(<img srcset="https://example.com/a.png 1x, javascript:alert(1) 2x, /b.png 3x">)
+
+      assert_equal(%(<img srcset="https://example.com/a.png 1x, /b.png 3x">), sanitizer.sanitize(html))
+    end
+
+    def test_it_removes_srcset_when_no_candidate_is_allowed
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)
+      html = %(<img src="/a.png" srcset="javascript:alert(1) 1x, ftp://example.com/b.png 2x">)
+
+      assert_equal(%(<img src="/a.png">), sanitizer.sanitize(html))
+    end
+
+    def test_it_normalizes_each_srcset_candidate
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config::RELAXED)
+      html = %(<img srcset="java&#9;script:alert(1) 1x,\n  /a.png\t2x,/b.png,  https://example.com/c.png 300w">)
+
+      assert_equal(
+        %(<img srcset="/a.png 2x, /b.png, https://example.com/c.png 300w">),
+        sanitizer.sanitize(html),
+      )
+    end
+
+    def test_it_keeps_commas_inside_srcset_urls
+      sanitizer = Selma::Sanitizer.new(
+        elements: ["img"],
+        attributes: { "img" => ["srcset"] },
+        protocols: { "img" => { "srcset" => ["data", "https"] } },
+      )
+      html = %(<img srcset="data:image/png;base64,iVBORw0KGgo= 1x, https://example.com/a.png 2x">)
+
+      assert_equal(
+        %(<img srcset="data:image/png;base64,iVBORw0KGgo= 1x, https://example.com/a.png 2x">),
+        sanitizer.sanitize(html),
+      )
+    end
+
+    def test_srcset_only_allows_relative_urls_without_protocols
+      sanitizer = Selma::Sanitizer.new(elements: ["img"], attributes: { "img" => ["srcset"] })
+      html = %(<img srcset="https://example.com/a.png 1x, /b.png 2x">)
+
+      assert_equal(%(<img srcset="/b.png 2x">), sanitizer.sanitize(html))
+    end
+
+    def test_it_checks_each_ping_url
+      sanitizer = Selma::Sanitizer.new(
+        elements: ["a"],
+        attributes: { "a" => ["href", "ping"] },
+        protocols: { "a" => { "href" => ["https", :relative] } },
+      )
+      html = %(<a href="/" ping="https://example.com/ping javascript:alert(1)\n/track">x</a>)
+
+      assert_equal(%(<a href="/" ping="https://example.com/ping /track">x</a>), sanitizer.sanitize(html))
+    end
+
+    def test_it_removes_ping_when_no_url_is_allowed
+      sanitizer = Selma::Sanitizer.new(
+        elements: ["a"],
+        attributes: { "a" => ["ping"] },
+        protocols: { "a" => { "ping" => ["https"] } },
+      )
+      html = %(<a ping="http://example.com/ping data:,x">x</a>)
+
+      assert_equal(%(<a>x</a>), sanitizer.sanitize(html))
+    end
+
+    def test_it_checks_the_url_of_a_meta_refresh
+      sanitizer = Selma::Sanitizer.new(
+        elements: ["meta"],
+        attributes: { "meta" => ["content", "http-equiv"] },
+        protocols: { "meta" => { "content" => ["https"] } },
+      )
+
+      [
+        %(<meta http-equiv="refresh" content="0; url=javascript:alert(1)">),
+        %(<meta http-equiv="Refresh" content="0;URL='javascript:alert(1)'">),
+        %(<meta http-equiv="refresh" content="0, java\tscript:alert(1)">),
+        %(<meta http-equiv="refresh" content="0; url = data:text/html,x">),
+      ].each do |html|
+        assert_equal(%(<meta http-equiv="#{html[/http-equiv="(\w+)"/, 1]}">), sanitizer.sanitize(html), html)
+      end
+
+      [
+        %(<meta http-equiv="refresh" content="5">),
+        %(<meta http-equiv="refresh" content="0; url=https://example.com/">),
+      ].each do |html|
+        assert_equal(html, sanitizer.sanitize(html))
+      end
+    end
+
+    def test_meta_content_without_a_url_passes_through
+      with_protocols = Selma::Sanitizer.new(
+        elements: ["meta"],
+        attributes: { "meta" => ["content", "http-equiv", "name"] },
+        protocols: { "meta" => { "content" => ["https"] } },
+      )
+      without_protocols = Selma::Sanitizer.new(
+        elements: ["meta"],
+        attributes: { "meta" => ["content", "http-equiv", "name"] },
+      )
+
+      [
+        %(<meta http-equiv="refresh" content="5">),
+        %(<meta http-equiv="refresh" content="0">),
+        %(<meta http-equiv="refresh" content="2.5;">),
+      ].each do |html|
+        assert_equal(html, with_protocols.sanitize(html), html)
+        assert_equal(html, without_protocols.sanitize(html), html)
+      end
+
+      html = %(<meta name="description" content="A page about https://example.com/">)
+
+      assert_equal(html, without_protocols.sanitize(html))
+    end
+
+    def test_meta_refresh_urls_need_protocols_to_be_absolute
+      sanitizer = Selma::Sanitizer.new(
+        elements: ["meta"],
+        attributes: { "meta" => ["content", "http-equiv"] },
+      )
+
+      assert_equal(%(<meta http-equiv="refresh" content="0; url=/next">), sanitizer.sanitize(%(<meta http-equiv="refresh" content="0; url=/next">)))
+      assert_equal(%(<meta http-equiv="refresh">), sanitizer.sanitize(%(<meta http-equiv="refresh" content="0; url=https://example.com/">)))
+    end
+  end
+end
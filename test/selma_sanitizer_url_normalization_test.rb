@@ -0,0 +1,95 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerUrlNormalizationTest < Minitest::Test
+    PROTOCOLS = ["http", "https", "mailto", :relative].freeze
+
+    CONFIG = {
+      elements: ["a", "button", "form", "img"],
+      attributes: {
+        "a" => ["href", "xlink:href"],
+        "button" => ["formaction"],
+        "form" => ["action"],
+        "img" => ["src", "srcset"],
+      },
+      protocols: {
+        "a" => { "href" => PROTOCOLS, "xlink:href" => PROTOCOLS },
+        "button" => { "formaction" => PROTOCOLS },
+        "form" => { "action" => PROTOCOLS },
+        "img" => { "src" => PROTOCOLS, "srcset" => PROTOCOLS },
+      },
+    }.freeze
+
+    # Each of these must lose its URL: once a browser has stripped and normalized it, it's `javascript:`.
+    REJECTED = {
+      tab: "java\tscript:alert(1)",
+      newline: "java\nscript:alert(1)",
+      carriage_return: "java\rscript:alert(1)",
+      null: "java\0script:alert(1)",
+      everything_at_once: "j\ta\nv\ra\0script:alert(1)",
+      leading_controls: "\u0001\u0008\u001f javascript:alert(1)",
+      trailing_controls: "javascript:alert(1)\u0001 \u001f",
+      tab_before_colon: "javascript\t:alert(1)",
+      uppercase: "JaVaScRiPt:alert(1)",
+      full_width: "ｊａｖａｓｃｒｉｐｔ：alert(1)",
+      full_width_scheme: "ｊａｖａｓｃｒｉｐｔ:alert(1)",
+      entity_tab: "java&Tab;script:alert(1)",
+      entity_newline: "java&NewLine;script:alert(1)",
+      decimal_tab: "java&#9;script:alert(1)",
+      hex_newline: "java&#x0A;script:alert(1)",
+      entity_colon: "javascript&colon;alert(1)",
+      data: "data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==",
+      vbscript: "vbscript:msgbox(1)",
+    }.freeze
+
+    # ...while these are harmless, and are kept after normalization.
+    KEPT = {
+      https: ["https://example.com/", "https://example.com/"],
+      leading_space: ["  https://example.com/", "https://example.com/"],
+      trailing_newline: ["https://example.com/\n", "https://example.com/"],
+      embedded_tab: ["ht\ttps://example.com/", "https://example.com/"],
+      uppercase: ["HTTPS://example.com/", "HTTPS://example.com/"],
+      absolute_path: ["/wiki/Special:Random", "/wiki/Special:Random"],
+      fragment: ["#fn:1", "#fn:1"],
+      path_with_fragment: ["somepage#fn:1", "somepage#fn:1"],
+      mailto: ["mailto:someone@example.com", "mailto:someone@example.com"],
+    }.freeze
+
+    def setup
+      @sanitizer = Selma::Sanitizer.new(CONFIG)
+    end
+
+    def test_it_rejects_urls_that_normalize_to_a_disallowed_protocol
+      REJECTED.each do |label, url|
+        CONFIG[:attributes].each do |element, attributes|
+          attributes.each do |attribute|
+            html, expected = if element == "img"
+              [%(<img #{attribute}="#{url}">), "<img>"]
+            else
+              [%(<#{element} #{attribute}="#{url}">x</#{element}>), "<#{element}>x</#{element}>"]
+            end
+
+            assert_equal(expected, @sanitizer.sanitize(html), "#{label}: #{element}[#{attribute}]")
+          end
+        end
+      end
+    end
+
+    def test_it_keeps_urls_that_normalize_to_an_allowed_protocol
+      KEPT.each do |label, (url, normalized)|
+        html = %(<a href="#{url}">x</a>)
+
+        assert_equal(%(<a href="#{normalized}">x</a>), @sanitizer.sanitize(html), label.to_s)
+      end
+    end
+
+    def test_it_does_not_normalize_other_attributes
+      sanitizer = Selma::Sanitizer.new(elements: ["a"], attributes: { "a" => ["title"] })
+      html = %(<a title="java\tscript: ">x</a>)
+
+      assert_equal(%(<a title="java\tscript: ">x</a>), sanitizer.sanitize(html))
+    end
+  end
+end
@@ -0,0 +1,194 @@
+# frozen_string_literal: true
+
+require "test_helper"
+
+module Selma
+  class SanitizerUrlRewritingTest < Minitest::Test
+    module Proxy
+      class << self
+        def call(url)
+          url.include?("tracker") ? nil : "https://proxy.example/?u=#{url}"
+        end
+      end
+    end
+
+    def test_it_resolves_relative_urls_against_the_base_url
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        base_url: "https://example.com/docs/guide?page=1#top",
+      )
+      sanitizer = Selma::Sanitizer.new(config)
+
+      {
+        %(<a href="/about">x</a>) => %(<a href="https://example.com/about">x</a>),
+        %(<a href="../faq/">x</a>) => %(<a href="https://example.com/faq/">x</a>),
+        %(<a href="./intro/../start?a=../b">x</a>) => %(<a href="https://example.com/docs/start?a=../b">x</a>),
+        %(<a href="/a/./b/../../c">x</a>) => %(<a href="https://example.com/c">x</a>),
+        %(<a href="../../../up">x</a>) => %(<a href="https://example.com/up">x</a>),
+        %(<a href="intro/start">x</a>) => %(<a href="https://example.com/docs/intro/start">x</a>),
+        %(<a href="//cdn.example.com/a.js">x</a>) => %(<a href="https://cdn.example.com/a.js">x</a>),
+        %(<a href="#section">x</a>) => %(<a href="#section">x</a>),
+        %(<a href="http://other.example/">x</a>) => %(<a href="http://other.example/">x</a>),
+        %(<img src="/a.png" srcset="/b.png 2x">) => %(<img src="https://example.com/a.png" srcset="https://example.com/b.png 2x">),
+      }.each do |html, expected|
+        assert_equal(expected, sanitizer.sanitize(html), html)
+      end
+    end
+
+    def test_it_checks_protocols_before_resolving
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, base_url: "https://example.com/"))
+
+      assert_equal(%(<a>x</a>), sanitizer.sanitize(%(<a href="javascript:alert(1)">x</a>)))
+    end
+
+    def test_it_rejects_a_base_url_that_is_not_absolute
+      ["/docs/", "example.com", "mailto:someone@example.com", 42].each do |base_url|
+        config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, base_url: base_url)
+
+        assert_raises(ArgumentError) { Selma::Sanitizer.new(config) }
+      end
+    end
+
+    def test_it_rewrites_urls_with_a_template
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: "https://camo.example/{hex(url)}")
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(
+        %(<img src="https://camo.example/68747470733a2f2f6578616d706c652e636f6d2f612e706e67">),
+        sanitizer.sanitize(%(<img src="https://example.com/a.png">)),
+      )
+    end
+
+    def test_templates_can_escape_and_insert_the_url_as_is
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        url_rewriter: "https://proxy.example/?u={escape(url)}&raw={url}",
+      )
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(
+        %(<img src="https://proxy.example/?u=https%3A%2F%2Fexample.com%2Fa%20b.png&amp;raw=https://example.com/a b.png">),
+        sanitizer.sanitize(%(<img src="https://example.com/a b.png">)),
+      )
+    end
+
+    def test_it_rejects_unknown_template_placeholders
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: "https://camo.example/{base64(url)}")
+      error = assert_raises(ArgumentError) { Selma::Sanitizer.new(config) }
+
+      assert_match(/unknown placeholder `\{base64\(url\)\}`/, error.message)
+
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: "https://camo.example/{url")
+
+      assert_raises(ArgumentError) { Selma::Sanitizer.new(config) }
+    end
+
+    def test_it_rewrites_urls_with_a_callable
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: Proxy))
+
+      assert_equal(
+        %(<a href="https://proxy.example/?u=https://example.com/">x</a>),
+        sanitizer.sanitize(%(<a href="https://example.com/">x</a>)),
+      )
+    end
+
+    def test_it_drops_urls_for_which_the_callable_returns_nil
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: Proxy))
+
+      assert_equal(%(<a>x</a>), sanitizer.sanitize(%(<a href="https://tracker.example/">x</a>)))
+      assert_equal(
+        %(<img srcset="https://proxy.example/?u=/a.png 1x">),
+        sanitizer.sanitize(%(<img srcset="/a.png 1x, https://tracker.example/b.png 2x">)),
+      )
+    end
+
+    def test_it_can_rewrite_only_some_attributes
+      config = Selma::Sanitizer::Config.merge(
+        Selma::Sanitizer::Config::RELAXED,
+        base_url: "https://example.com/",
+        url_rewriter: { img: { src: "https://camo.example/{hex(url)}" } },
+      )
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(
+        %(<a href="https://example.com/a.png"><img src="https://camo.example/68747470733a2f2f6578616d706c652e636f6d2f612e706e67"></a>),
+        sanitizer.sanitize(%(<a href="/a.png"><img src="/a.png"></a>)),
+      )
+    end
+
+    def test_it_leaves_fragments_alone
+      config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: "https://camo.example/{hex(url)}")
+      sanitizer = Selma::Sanitizer.new(config)
+
+      assert_equal(%(<a href="#fn-1">x</a>), sanitizer.sanitize(%(<a href="#fn-1">x</a>)))
+    end
+
+    def test_it_accepts_callables_that_cannot_be_shared_without_changing_them
+      not_shareable = Object.new
+      rewriter = ->(url) { "#{url}##{not_shareable.object_id}" }
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: rewriter))
+
+      refute_predicate(rewriter, :frozen?)
+      refute_predicate(not_shareable, :frozen?)
+      refute(Ractor.shareable?(sanitizer))
+      assert_equal(
+        %(<a href="https://example.com/##{not_shareable.object_id}">x</a>),
+        sanitizer.sanitize(%(<a href="https://example.com/">x</a>)),
+      )
+    end
+
+    def test_it_rejects_rewriters_that_are_not_callable
+      [42, { "img" => ["src"] }].each do |url_rewriter|
+        config = Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: url_rewriter)
+
+        assert_raises(ArgumentError) { Selma::Sanitizer.new(config) }
+      end
+    end
+
+    def test_sanitizers_with_shareable_callables_stay_shareable
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: Proxy))
+
+      assert(Ractor.shareable?(sanitizer))
+
+      rewriter = Ractor.make_shareable(->(url) { url.upcase })
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: rewriter))
+
+      assert(Ractor.shareable?(sanitizer))
+    end
+
+    def test_rewriters_keep_the_callables_of_a_sanitizer_nothing_else_refers_to
+      rewriter = Selma::Rewriter.new(
+        sanitizer: Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(
+          Selma::Sanitizer::Config::RELAXED,
+          url_rewriter: ->(url) { "https://proxy.example/?u=#{url}" },
+        )),
+      )
+
+      GC.start(full_mark: true, immediate_sweep: true)
+
+      assert_equal(
+        %(<a href="https://proxy.example/?u=https://example.com/">x</a>),
+        rewriter.rewrite(%(<a href="https://example.com/">x</a>)),
+      )
+    end
+
+    def test_exceptions_raised_by_a_callable_reach_the_caller_unchanged
+      error_class = Class.new(StandardError)
+      rewriter = ->(_url) { raise error_class, "no proxy today" }
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: rewriter))
+      html = %(<a href="https://example.com/">x</a>)
+
+      error = assert_raises(error_class) { sanitizer.sanitize(html) }
+
+      assert_equal("no proxy today", error.message)
+      assert_raises(error_class) { Selma::Rewriter.new(sanitizer: sanitizer).rewrite(html) }
+    end
+
+    def test_throw_from_a_callable_is_not_swallowed
+      rewriter = ->(_url) { throw(:stop, :thrown) }
+      sanitizer = Selma::Sanitizer.new(Selma::Sanitizer::Config.merge(Selma::Sanitizer::Config::RELAXED, url_rewriter: rewriter))
+
+      assert_equal(:thrown, catch(:stop) { sanitizer.sanitize(%(<img src="https://example.com/a.png">)) })
+    end
+  end
+end
@@ -42,4 +42,132 @@ class SelmaSelectorTest < Minitest::Test
       out,
     )
   end
+
+  def test_that_it_accepts_an_array_of_selectors
+    Selma::Selector.new(match_element: ["a[href]", "img[src]"], match_text_within: ["p", "li"])
+  end
+
+  def test_that_it_raises_against_invalid_css_in_an_array
+    assert_raises(ArgumentError) do
+      Selma::Selector.new(match_element: ["a[href]", %(img[src=])])
+    end
+  end
+
+  def test_that_it_raises_against_an_empty_array
+    assert_raises(ArgumentError) do
+      Selma::Selector.new(match_element: [])
+    end
+  end
+
+  def test_that_it_raises_against_non_string_selectors
+    assert_raises(TypeError) do
+      Selma::Selector.new(match_element: :a)
+    end
+  end
+
+  class DispatchingHandler
+    SELECTOR = Selma::Selector.new(match_element: ["a[href]", "img[src]"])
+
+    attr_reader :matches
+
+    def initialize
+      @matches = []
+    end
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element, matched_selector)
+      @matches << [element.tag_name, matched_selector]
+      element["data-selector"] = matched_selector
+    end
+  end
+
+  def test_matched_selector_is_passed_to_handle_element
+    frag = %(<a href="/">link</a><a>anchor</a><img src="/a.png">)
+    handler = DispatchingHandler.new
+    out = Selma::Rewriter.new(sanitizer: nil, handlers: [handler]).rewrite(frag)
+
+    assert_equal(
+      %(<a href="/" data-selector="a[href]">link</a><a>anchor</a><img src="/a.png" data-selector="img[src]">),
+      out,
+    )
+    assert_equal([["a", "a[href]"], ["img", "img[src]"]], handler.matches)
+  end
+
+  class SingleArgumentHandler
+    SELECTOR = Selma::Selector.new(match_element: ["strong", "em"])
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element)
+      element["class"] = "matched"
+    end
+  end
+
+  def test_single_argument_handlers_still_work_with_arrays
+    frag = %(<strong>a</strong><em>b</em><span>c</span>)
+    out = Selma::Rewriter.new(sanitizer: nil, handlers: [SingleArgumentHandler.new]).rewrite(frag)
+
+    assert_equal(%(<strong class="matched">a</strong><em class="matched">b</em><span>c</span>), out)
+  end
+
+  class KeywordArgumentHandler
+    SELECTOR = Selma::Selector.new(match_element: ["strong", "em"])
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element, prefix: "matched", **options)
+      element["class"] = prefix
+    end
+  end
+
+  class SplatHandler
+    SELECTOR = Selma::Selector.new(match_element: ["strong", "em"])
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_element(element, *rest)
+      element["class"] = rest.first
+    end
+  end
+
+  def test_matched_selector_is_only_passed_as_a_positional_argument
+    frag = %(<strong>a</strong><em>b</em>)
+
+    assert_equal(
+      %(<strong class="matched">a</strong><em class="matched">b</em>),
+      Selma::Rewriter.new(sanitizer: nil, handlers: [KeywordArgumentHandler.new]).rewrite(frag),
+    )
+    assert_equal(
+      %(<strong class="strong">a</strong><em class="em">b</em>),
+      Selma::Rewriter.new(sanitizer: nil, handlers: [SplatHandler.new]).rewrite(frag),
+    )
+  end
+
+  class TextListHandler
+    SELECTOR = Selma::Selector.new(match_text_within: ["p", ".shout"])
+
+    def selector
+      SELECTOR
+    end
+
+    def handle_text_chunk(text)
+      text.replace(text.to_s.upcase, as: :text)
+    end
+  end
+
+  def test_text_matching_several_selectors_is_handled_once
+    frag = %(<p class="shout">hi</p><div>bye</div>)
+    out = Selma::Rewriter.new(sanitizer: nil, handlers: [TextListHandler.new]).rewrite(frag)
+
+    assert_equal(%(<p class="shout">HI</p><div>bye</div>), out)
+  end
 end